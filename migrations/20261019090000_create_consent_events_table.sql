@@ -0,0 +1,25 @@
+-- Add migration script here
+CREATE TABLE consent_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    source TEXT NOT NULL,
+    consent_text_version TEXT NOT NULL
+);
+
+-- The audit trail is append-only: rows can be added, and are only
+-- ever removed together with their subscriber on erasure.
+CREATE FUNCTION reject_consent_event_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+    BEFORE UPDATE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_update();
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE bounce_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    email TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    bounce_type TEXT NULL,
+    description TEXT NULL,
+    payload TEXT NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX bounce_events_email_idx ON bounce_events (lower(email));
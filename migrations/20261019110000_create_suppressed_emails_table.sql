@@ -0,0 +1,20 @@
+-- Add migration script here
+CREATE TABLE suppressed_emails (
+    -- Always stored lowercased
+    email TEXT NOT NULL,
+    PRIMARY KEY (email),
+    source TEXT NOT NULL
+        CHECK (source IN ('manual', 'bounce', 'complaint', 'legal')),
+    reason TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- Carry over addresses that have already bounced or complained
+INSERT INTO suppressed_emails (email, source, reason, created_at)
+SELECT DISTINCT ON (lower(email))
+    lower(email),
+    CASE status WHEN 'bounced' THEN 'bounce' ELSE 'complaint' END,
+    NULL,
+    now()
+FROM subscriptions
+WHERE status IN ('bounced', 'complained');
@@ -0,0 +1,35 @@
+-- Add migration script here
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+-- Token embedded in every issue to let subscribers manage themselves
+ALTER TABLE subscriptions ADD COLUMN preferences_token TEXT NULL;
+UPDATE subscriptions SET preferences_token = encode(gen_random_bytes(20), 'hex');
+ALTER TABLE subscriptions ALTER COLUMN preferences_token SET NOT NULL;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_preferences_token_key UNIQUE (preferences_token);
+
+ALTER TABLE subscriptions ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'every_issue'
+    CHECK (delivery_frequency IN ('every_issue', 'weekly_digest'));
+ALTER TABLE subscriptions ADD COLUMN paused_until timestamptz NULL;
+
+CREATE TABLE topics (
+    topic_id uuid NOT NULL,
+    PRIMARY KEY (topic_id),
+    name TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL
+);
+
+-- Subscribers receive every topic unless they opt out of it
+CREATE TABLE topic_opt_outs (
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    topic_id uuid NOT NULL
+        REFERENCES topics (topic_id) ON DELETE CASCADE,
+    PRIMARY KEY (subscriber_id, topic_id)
+);
+
+-- Issues without a topic go out to everyone
+ALTER TABLE newsletter_issues ADD COLUMN topic_id uuid NULL
+    REFERENCES topics (topic_id) ON DELETE SET NULL;
+
+-- Weekly digest subscribers get their deliveries held until the next batch
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
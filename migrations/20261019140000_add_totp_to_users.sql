@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Base32-encoded shared secret, only set once enrollment has been confirmed
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- Last accepted time step, to stop a code from being replayed
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE totp_recovery_codes (
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
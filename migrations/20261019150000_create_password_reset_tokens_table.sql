@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE password_reset_tokens (
+    -- SHA-256 of the token sent by email, hex-encoded
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL
+);
+
+-- Sessions created before this point in time are no longer valid
+ALTER TABLE users ADD COLUMN sessions_invalidated_at timestamptz NULL;
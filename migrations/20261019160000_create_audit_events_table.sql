@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE audit_events (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    event_type TEXT NOT NULL,
+    user_id uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    username TEXT NULL,
+    ip_address TEXT NULL,
+    details TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
@@ -0,0 +1,16 @@
+-- Add migration script here
+-- One row per login, so users can see and revoke their sessions.
+-- The session state itself lives in Redis.
+CREATE TABLE user_sessions (
+    session_id uuid NOT NULL,
+    PRIMARY KEY (session_id),
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    revoked_at timestamptz NULL
+);
+
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
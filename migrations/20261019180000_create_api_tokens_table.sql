@@ -0,0 +1,16 @@
+-- Add migration script here
+-- Personal API tokens used as bearer tokens on `/api/v1`.
+-- Only a SHA-256 hash of the token is stored, it is shown once on creation.
+CREATE TABLE api_tokens (
+    api_token_id uuid NOT NULL,
+    PRIMARY KEY (api_token_id),
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,37 @@
+-- Where we notify other systems, e.g. our CRM, about subscribers and issues
+CREATE TABLE webhook_endpoints(
+    webhook_endpoint_id uuid PRIMARY KEY,
+    url TEXT NOT NULL,
+    -- Kept in clear text, we need it to sign every delivery
+    secret TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    removed_at timestamptz
+);
+
+CREATE TABLE webhook_events(
+    webhook_event_id uuid PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    -- JSON document
+    payload TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL
+);
+
+-- One row per event and endpoint, it doubles as the delivery log
+CREATE TABLE webhook_deliveries(
+    webhook_event_id uuid NOT NULL
+        REFERENCES webhook_events (webhook_event_id) ON DELETE CASCADE,
+    webhook_endpoint_id uuid NOT NULL
+        REFERENCES webhook_endpoints (webhook_endpoint_id) ON DELETE CASCADE,
+    -- `pending`, `delivered`, `failed` or `cancelled`
+    status TEXT NOT NULL,
+    n_attempts SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL,
+    last_attempt_at timestamptz,
+    last_response_status SMALLINT,
+    last_error TEXT,
+    PRIMARY KEY (webhook_event_id, webhook_endpoint_id)
+);
+
+CREATE INDEX webhook_deliveries_pending_idx
+    ON webhook_deliveries (execute_after)
+    WHERE status = 'pending';
@@ -0,0 +1,2 @@
+-- Expired keys are looked up by age to be deleted
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,2 @@
+-- Keys saved before this migration have no fingerprint and are not checked.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
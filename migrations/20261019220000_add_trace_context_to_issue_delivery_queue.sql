@@ -0,0 +1,3 @@
+-- W3C traceparent of the request that published the issue, so that the
+-- worker spans delivering it join the same trace.
+ALTER TABLE issue_delivery_queue ADD COLUMN traceparent TEXT NULL;
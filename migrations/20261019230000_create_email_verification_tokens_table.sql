@@ -0,0 +1,13 @@
+-- Add migration script here
+-- Users choose their own email address: it is only stored in `users` once
+-- a link sent to it has been followed
+CREATE TABLE email_verification_tokens (
+    -- SHA-256 of the token sent by email, hex-encoded
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    user_id uuid NOT NULL
+        REFERENCES users (user_id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Store invitation tokens like password reset tokens: only a SHA-256 hash,
+-- hex-encoded. Links that were already sent keep working.
+UPDATE user_invitations
+SET invitation_token = encode(sha256(convert_to(invitation_token, 'UTF8')), 'hex');
+ALTER TABLE user_invitations RENAME COLUMN invitation_token TO token_hash;
@@ -0,0 +1,53 @@
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+//----------------------------------------------------------------
+/// Security-relevant things we want a durable trace of.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditEventType {
+    LoginLockout,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::LoginLockout => "login_lockout",
+        }
+    }
+}
+
+pub struct AuditEvent<'a> {
+    pub event_type: AuditEventType,
+    pub user_id: Option<Uuid>,
+    pub username: Option<&'a str>,
+    pub ip_address: Option<&'a str>,
+    pub details: Option<String>,
+}
+//----------------------------------------------------------------
+#[tracing::instrument(
+    name = "Record audit event",
+    skip(executor, event),
+    fields(event_type = %event.event_type.as_str())
+)]
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    event: &AuditEvent<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (
+            id, event_type, user_id, username, ip_address, details, occurred_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        event.event_type.as_str(),
+        event.user_id,
+        event.username,
+        event.ip_address,
+        event.details,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
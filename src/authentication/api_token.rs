@@ -0,0 +1,159 @@
+use crate::authentication::Role;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Makes leaked tokens easy to recognise, e.g. by secret scanners.
+const API_TOKEN_PREFIX: &str = "nlt_";
+
+//----------------------------------------------------------------
+pub struct ApiToken {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Who a bearer token acts on behalf of.
+pub struct ApiTokenOwner {
+    pub user_id: Uuid,
+    pub role: Role,
+}
+
+/// Store a new token for the user and return it in clear text.
+/// Only its hash is persisted, so it cannot be shown again.
+#[tracing::instrument(name = "Create API token", skip(db_pool))]
+pub async fn create_user_api_token(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_api_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (api_token_id, user_id, name, token_hash, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(&token),
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(Secret::new(token))
+}
+
+#[tracing::instrument(name = "List API tokens", skip(db_pool))]
+pub async fn list_user_api_tokens(db_pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiToken>, anyhow::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT api_token_id, name, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve API tokens.")
+}
+
+/// Returns `false` if the token is not one of the user's active tokens.
+#[tracing::instrument(name = "Revoke API token", skip(db_pool))]
+pub async fn revoke_user_api_token(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    api_token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET revoked_at = now()
+        WHERE api_token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        api_token_id,
+        user_id,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to revoke the API token.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Look up the owner of an active token and mark the token as used.
+/// Tokens of deactivated users are rejected like revoked ones.
+#[tracing::instrument(name = "Authenticate API token", skip_all)]
+pub async fn authenticate_api_token(
+    db_pool: &PgPool,
+    token: &str,
+) -> Result<Option<ApiTokenOwner>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t SET last_used_at = now()
+        FROM users u
+        WHERE t.token_hash = $1
+        AND t.revoked_at IS NULL
+        AND u.user_id = t.user_id
+        AND u.is_active
+        RETURNING t.user_id, u.role
+        "#,
+        hash_api_token(token)
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to look up the API token.")?;
+    row.map(|r| {
+        Ok(ApiTokenOwner {
+            user_id: r.user_id,
+            role: Role::try_from(r.role).map_err(anyhow::Error::msg)?,
+        })
+    })
+    .transpose()
+}
+
+// Tokens are long and random, a fast hash is enough to protect them at rest
+// and lets us look them up directly.
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a random token: the prefix followed by 40 case-sensitive
+/// alphanumeric characters.
+fn generate_api_token() -> String {
+    let mut rng = thread_rng();
+    let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    format!("{}{}", API_TOKEN_PREFIX, random)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_api_token, hash_api_token, API_TOKEN_PREFIX};
+
+    #[test]
+    fn tokens_are_prefixed_and_random() {
+        let token = generate_api_token();
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(token.len(), API_TOKEN_PREFIX.len() + 40);
+        assert_ne!(token, generate_api_token());
+    }
+
+    #[test]
+    fn the_hash_does_not_reveal_the_token() {
+        let token = generate_api_token();
+        let hash = hash_api_token(&token);
+        assert_eq!(hash, hash_api_token(&token));
+        assert!(!hash.contains(&token[API_TOKEN_PREFIX.len()..]));
+    }
+}
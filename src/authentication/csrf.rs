@@ -0,0 +1,217 @@
+use crate::session_state::TypedSession;
+use crate::utils::{constant_time_eq, e500};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorPayloadTooLarge;
+use actix_web::http::header::{ContentType, CONTENT_TYPE};
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use actix_web_lab::middleware::Next;
+use htmlescape::encode_minimal;
+use rand::{thread_rng, RngCore};
+
+// Where the token can be submitted: a form field, url-encoded or multipart,
+// or a header for scripted clients. Never the query string, it ends up in
+// access logs, the browser history and `Referer` headers.
+pub const CSRF_FIELD_NAME: &str = "csrf_token";
+pub const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+// A little more than the largest upload, see `UploadForm` of the suppressions.
+const MAX_MULTIPART_BODY_BYTES: usize = 6 * 1024 * 1024;
+
+//----------------------------------------------------------------
+/// The synchronizer token of the current session, available to the
+/// handlers of the `/admin` scope to embed in their forms.
+#[derive(Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Hidden input to add to every form posting to the admin area.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input hidden type="text" name="{}" value="{}">"#,
+            CSRF_FIELD_NAME,
+            encode_minimal(&self.0)
+        )
+    }
+}
+
+pub fn generate_csrf_token() -> String {
+    let mut token = [0u8; 32];
+    thread_rng().fill_bytes(&mut token);
+    hex::encode(token)
+}
+//----------------------------------------------------------------
+/// Reject state-changing requests that do not carry the session's CSRF token
+/// with a 403 page.
+/// It relies on the session set up by `reject_anonymous_users`, so it must be
+/// registered inside the `/admin` scope, after it.
+pub async fn verify_csrf_token(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected_token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        // Logging in sets one, but sessions from before this check had none
+        None => {
+            let token = generate_csrf_token();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+    let is_safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    if !is_safe_method {
+        let submitted_token = submitted_csrf_token(&mut req).await?;
+        if !submitted_token.is_some_and(|t| constant_time_eq(&t, &expected_token)) {
+            tracing::warn!(
+                path = req.path(),
+                "Rejected a request with a missing or invalid CSRF token."
+            );
+            return Ok(req.into_response(forbidden_page()).map_into_right_body());
+        }
+    }
+    req.extensions_mut().insert(CsrfToken(expected_token));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Look for the token in the header, then in the body of url-encoded and
+/// multipart forms.
+async fn submitted_csrf_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(token) = req
+        .headers()
+        .get(CSRF_HEADER_NAME)
+        .and_then(|h| h.to_str().ok())
+    {
+        return Ok(Some(token.to_owned()));
+    }
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    // Reading the body consumes it: put it back for the handler
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        let body = req.extract::<web::Bytes>().await?;
+        let token = find_field(&body);
+        req.set_payload(body.into());
+        Ok(token)
+    } else if content_type.starts_with("multipart/form-data") {
+        let body = req
+            .extract::<web::Payload>()
+            .await?
+            .to_bytes_limited(MAX_MULTIPART_BODY_BYTES)
+            .await
+            .map_err(|_| ErrorPayloadTooLarge("The upload is too large."))??;
+        let token = find_multipart_field(&content_type, &body);
+        req.set_payload(body.into());
+        Ok(token)
+    } else {
+        Ok(None)
+    }
+}
+
+fn find_field(urlencoded: &[u8]) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(urlencoded)
+        .ok()?
+        .into_iter()
+        .find(|(k, _)| k == CSRF_FIELD_NAME)
+        .map(|(_, v)| v)
+}
+
+/// The value of the token part of a `multipart/form-data` body.
+fn find_multipart_field(content_type: &str, body: &[u8]) -> Option<String> {
+    let boundary = content_type
+        .split(';')
+        .find_map(|p| p.trim().strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delimiter = format!("--{}", boundary);
+    let field_name = format!("name=\"{}\"", CSRF_FIELD_NAME);
+    // Uploaded files need not be UTF-8, the headers and the token are
+    String::from_utf8_lossy(body)
+        .split(delimiter.as_str())
+        .find_map(|part| {
+            let (headers, value) = part.split_once("\r\n\r\n")?;
+            let is_token = headers.lines().any(|l| {
+                l.to_ascii_lowercase().starts_with("content-disposition:")
+                    && l.split(';').any(|p| p.trim() == field_name)
+            });
+            is_token.then(|| value.strip_suffix("\r\n").unwrap_or(value).to_owned())
+        })
+}
+
+fn forbidden_page() -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>This form has expired or was not submitted from this site.</p>
+    <p>Go back, reload the page and try again.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_field, find_multipart_field};
+    use claim::{assert_none, assert_some_eq};
+
+    #[test]
+    fn the_token_is_found_among_other_fields() {
+        assert_some_eq!(
+            find_field(b"email=a%40example.com&csrf_token=abc123&role=viewer"),
+            "abc123".to_string()
+        );
+    }
+
+    #[test]
+    fn a_form_without_the_token_yields_nothing() {
+        assert_none!(find_field(b"email=a%40example.com"));
+        assert_none!(find_field(b""));
+    }
+
+    #[test]
+    fn the_token_is_found_among_the_parts_of_a_multipart_body() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            abc123\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"csrf_token\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            a@example.com\r\n\
+            --XyZ--\r\n";
+        assert_some_eq!(
+            find_multipart_field("multipart/form-data; boundary=XyZ", body.as_bytes()),
+            "abc123".to_string()
+        );
+    }
+
+    #[test]
+    fn a_file_named_like_the_token_is_not_the_token() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"csrf_token\"\r\n\r\n\
+            abc123\r\n\
+            --XyZ--\r\n";
+        assert_none!(find_multipart_field(
+            "multipart/form-data; boundary=XyZ",
+            body.as_bytes()
+        ));
+    }
+}
@@ -1,10 +1,19 @@
 use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::body::MessageBody;
+use actix_web::body::{EitherBody, MessageBody};
 use actix_web_lab::middleware::Next;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage};
+use crate::authentication::{authenticate_api_token, Role};
+use crate::configuration::SessionSettings;
 use crate::session_state::TypedSession;
+use crate::user_sessions::{revoke_user_session, touch_user_session};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::{Method, StatusCode};
+use actix_web_flash_messages::FlashMessage;
 use actix_web::error::InternalError;
-use crate::utils::{e500, see_other};
+use crate::utils::{e500, json_error, see_other};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
 use std::ops::Deref;
 
@@ -25,20 +34,186 @@ impl Deref for UserId {
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>
-) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
-        },
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
         None => {
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in.");
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is not registered as application data.")
+        .map_err(e500)?;
+    let session_settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .context("The session settings are not registered as application data.")
+        .map_err(e500)?;
+    let logged_in_at = session.get_logged_in_at().map_err(e500)?.unwrap_or(0);
+    let last_seen_at = session.get_last_seen_at().map_err(e500)?.unwrap_or(logged_in_at);
+    let now = Utc::now().timestamp_millis();
+    if is_expired(now, logged_in_at, last_seen_at, session_settings) {
+        if let Some(session_id) = session.get_session_id().map_err(e500)? {
+            revoke_user_session(db_pool.get_ref(), user_id, session_id)
+                .await
+                .map_err(e500)?;
+        }
+        session.log_out();
+        // Bring the user back to the page they asked for once logged in again
+        let location = match req.uri().path_and_query() {
+            Some(requested) if req.method() == Method::GET => format!(
+                "/login?next={}",
+                urlencoding::encode(requested.as_str())
+            ),
+            _ => "/login".to_string(),
+        };
+        // A response rather than an error, so the flash message cookie gets set
+        FlashMessage::info("Your session expired. Please log in again.").send();
+        return Ok(req.into_response(see_other(&location)).map_into_right_body());
+    }
+    session.touch().map_err(e500)?;
+    // Deactivated users are logged out on their next request, and so are
+    // revoked sessions and sessions opened before the user's sessions
+    // were invalidated.
+    let session_is_active = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => touch_user_session(db_pool, user_id, session_id)
+            .await
+            .map_err(e500)?,
+        None => false,
+    };
+    match get_active_user(db_pool, user_id).await.map_err(e500)? {
+        Some(user)
+            if session_is_active
+                && !matches!(
+                    user.sessions_invalidated_at,
+                    Some(t) if t.timestamp_millis() > logged_in_at
+                ) =>
+        {
+            let role = Role::try_from(user.role).map_err(e500)?;
+            req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        _ => {
+            session.log_out();
+            let response = see_other("/login");
+            let e = anyhow::anyhow!(
+                "The user is unknown, deactivated or their session was revoked."
+            );
             Err(InternalError::from_response(e, response).into())
         }
     }
-}
\ No newline at end of file
+}
+
+/// Authenticate requests to the JSON API with an `Authorization: Bearer`
+/// API token, answering 401 with a JSON body otherwise.
+/// Like `reject_anonymous_users`, it makes `UserId` and `Role` available
+/// to the handlers.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_owned());
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is not registered as application data.")
+        .map_err(e500)?;
+    let owner = match token {
+        Some(token) => authenticate_api_token(db_pool, &token).await.map_err(e500)?,
+        None => None,
+    };
+    match owner {
+        Some(owner) => {
+            req.extensions_mut().insert(UserId(owner.user_id));
+            req.extensions_mut().insert(owner.role);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        None => {
+            let mut response = json_error(
+                StatusCode::UNAUTHORIZED,
+                "A valid API token is required in the Authorization header.",
+            );
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+// Idle for too long, or open for too long altogether.
+// All timestamps are Unix timestamps in milliseconds.
+fn is_expired(now: i64, logged_in_at: i64, last_seen_at: i64, settings: &SessionSettings) -> bool {
+    let idle_timeout = settings.idle_timeout().as_millis() as i64;
+    let absolute_timeout = settings.absolute_timeout().as_millis() as i64;
+    now - last_seen_at > idle_timeout || now - logged_in_at > absolute_timeout
+}
+
+struct ActiveUser {
+    role: String,
+    sessions_invalidated_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get active user", skip(db_pool))]
+async fn get_active_user(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<ActiveUser>, anyhow::Error> {
+    sqlx::query_as!(
+        ActiveUser,
+        r#"
+        SELECT role, sessions_invalidated_at
+        FROM users
+        WHERE user_id = $1 AND is_active
+        "#,
+        user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the current user.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_expired;
+    use crate::configuration::SessionSettings;
+
+    const MINUTE: i64 = 60 * 1000;
+    const HOUR: i64 = 60 * MINUTE;
+
+    fn settings() -> SessionSettings {
+        SessionSettings {
+            idle_timeout_minutes: 30,
+            absolute_timeout_hours: 12,
+        }
+    }
+
+    #[test]
+    fn an_active_session_is_not_expired() {
+        let now = 10 * HOUR;
+        assert!(!is_expired(now, now - 2 * HOUR, now - 5 * MINUTE, &settings()));
+    }
+
+    #[test]
+    fn an_idle_session_expires() {
+        let now = 10 * HOUR;
+        assert!(is_expired(now, now - 2 * HOUR, now - 31 * MINUTE, &settings()));
+    }
+
+    #[test]
+    fn a_session_expires_after_its_lifetime_however_active() {
+        let now = 20 * HOUR;
+        assert!(is_expired(now, now - 13 * HOUR, now - MINUTE, &settings()));
+    }
+}
@@ -1,7 +1,33 @@
+mod api_token;
+mod csrf;
 mod middleware;
 mod password;
+mod password_policy;
+mod password_reset;
+mod permission;
+mod role;
+mod throttle;
+mod totp;
+pub use api_token::{
+    authenticate_api_token, create_user_api_token, list_user_api_tokens, revoke_user_api_token,
+    ApiToken, ApiTokenOwner,
+};
 pub use password::{
-    change_password,validate_credentials,
-    AuthError, Credentials
+    change_password, compute_password_hash, validate_credentials,
+    validate_new_password, AuthError, Credentials
+};
+pub use password_reset::{
+    create_reset_token, get_reset_recipient, get_reset_token_username, is_valid_reset_token,
+    reset_password,
+};
+pub use csrf::{
+    generate_csrf_token, verify_csrf_token, CsrfToken, CSRF_FIELD_NAME, CSRF_HEADER_NAME,
 };
-pub use middleware::{reject_anonymous_users, UserId};
\ No newline at end of file
+pub use middleware::{reject_anonymous_users, reject_invalid_api_tokens, UserId};
+pub use permission::{Permission, RequirePermission};
+pub use role::Role;
+pub use throttle::{LockoutScope, LoginThrottle, LoginThrottleDecision};
+pub use totp::{
+    disable_totp, enable_totp, generate_totp_code, generate_totp_secret, get_totp_secret,
+    is_valid_totp_code, provisioning_qr_code, provisioning_uri, verify_second_factor,
+};
\ No newline at end of file
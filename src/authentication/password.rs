@@ -1,3 +1,5 @@
+use crate::authentication::password_policy::check_password_policy;
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use secrecy::{Secret, ExposeSecret};
@@ -6,7 +8,11 @@ use argon2::{
     Argon2, PasswordHash, PasswordHasher,
     PasswordVerifier,Algorithm, Version, Params
 };
+use once_cell::sync::Lazy;
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::Instrument;
 
 //----------------------------------------------------------------
 #[derive(thiserror::Error, Debug)]
@@ -22,35 +28,132 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, db_pool))]
+#[tracing::instrument(
+    name = "Validate credentials",
+    skip(credentials, db_pool, hashing_settings)
+)]
 pub async fn validate_credentials(
     credentials: Credentials,
     db_pool: &PgPool,
+    hashing_settings: &PasswordHashingSettings,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = None;
     if let Some((stored_user_id, stored_password_hash)) =
         get_store_credentials(&credentials.username, &db_pool)
             .await?
     {
         user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash
+        expected_password_hash = Some(stored_password_hash)
     }
 
+    let verified_password_hash = expected_password_hash.clone();
+    let password = credentials.password.clone();
+    let settings = hashing_settings.clone();
     spawn_blocking_with_tracing(move || {
+        // Unknown usernames take as long to check as real ones
+        let expected_password_hash = match expected_password_hash {
+            Some(hash) => hash,
+            None => dummy_password_hash(&settings)?,
+        };
         verify_password_hash(expected_password_hash, credentials.password)
     })
     .await
     .context("Failed to spawn blocking task")??;
 
-    user_id
-        .ok_or_else(|| anyhow::anyhow!("Unknown username"))
-        .map_err(AuthError::InvalidCredentials)
+    let (Some(user_id), Some(verified_password_hash)) = (user_id, verified_password_hash) else {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username")));
+    };
+    // Upgrade outdated hashes while we have the password at hand,
+    // in the background so logging in doesn't get any slower
+    if needs_rehash(&verified_password_hash, hashing_settings) {
+        tokio::spawn(
+            rehash_password(
+                user_id,
+                verified_password_hash,
+                password,
+                hashing_settings.clone(),
+                db_pool.clone(),
+            )
+            .instrument(tracing::Span::current()),
+        );
+    }
+    Ok(user_id)
+}
+
+/// Whether `password_hash` was computed with another algorithm, version
+/// or cost than the ones currently configured.
+fn needs_rehash(password_hash: &Secret<String>, settings: &PasswordHashingSettings) -> bool {
+    let password_hash = match PasswordHash::new(password_hash.expose_secret()) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+    let params = match Params::try_from(&password_hash) {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13 as u32)
+        || params.m_cost() != settings.memory_cost_kib
+        || params.t_cost() != settings.iterations
+        || params.p_cost() != settings.parallelism
+}
+
+async fn rehash_password(
+    user_id: uuid::Uuid,
+    old_password_hash: Secret<String>,
+    password: Secret<String>,
+    settings: PasswordHashingSettings,
+    db_pool: PgPool,
+) {
+    let result = async {
+        let password_hash =
+            spawn_blocking_with_tracing(move || compute_password_hash(password, &settings))
+                .await?
+                .context("Failed to hash password")?;
+        // Leave the hash alone if the password was changed in the meantime
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $1
+            WHERE user_id = $2 AND password_hash = $3
+            "#,
+            password_hash.expose_secret(),
+            user_id,
+            old_password_hash.expose_secret(),
+        )
+        .execute(&db_pool)
+        .await
+        .context("Failed to store the upgraded password hash")?;
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to upgrade an outdated password hash.",
+        );
+    }
+}
+
+/// A hash of nothing in particular, computed with the configured cost so that
+/// verifying a password against it takes as long as against a real hash.
+/// It is only computed once for each cost.
+fn dummy_password_hash(settings: &PasswordHashingSettings) -> Result<Secret<String>, anyhow::Error> {
+    // Keyed by memory cost, iterations and parallelism
+    type DummyPasswordHashes = HashMap<(u32, u32, u32), Secret<String>>;
+    static DUMMY_PASSWORD_HASHES: Lazy<Mutex<DummyPasswordHashes>> = Lazy::new(Default::default);
+    let cost = (settings.memory_cost_kib, settings.iterations, settings.parallelism);
+    let mut hashes = DUMMY_PASSWORD_HASHES
+        .lock()
+        .map_err(|_| anyhow::anyhow!("The dummy password hashes are poisoned"))?;
+    if let Some(hash) = hashes.get(&cost) {
+        return Ok(hash.clone());
+    }
+    let hash = compute_password_hash(Secret::new("dummy password".to_string()), settings)?;
+    hashes.insert(cost, hash.clone());
+    Ok(hash)
 }
 
 #[tracing::instrument(
@@ -82,7 +185,7 @@ async fn get_store_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1 
+        WHERE username = $1 AND is_active
         "#,
         username
     )
@@ -93,17 +196,40 @@ async fn get_store_credentials(
 
     Ok(row)
 }
+/// Rules every new password must follow, wherever it is set from.
+/// Returns one message per failed rule.
+pub fn validate_new_password(
+    new_password: &Secret<String>,
+    new_password_check: &Secret<String>,
+    username: &str,
+    policy: &PasswordPolicySettings,
+) -> Result<(), Vec<String>> {
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        return Err(vec![
+            "You entered two different new passwords - the field values must match.".into(),
+        ]);
+    }
+    let violations = check_password_policy(new_password, username, policy);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
 #[tracing::instrument(
     name = "Change password",
-    skip(password, db_pool)
+    skip(password, db_pool, hashing_settings)
 )]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
     db_pool: &PgPool,
+    hashing_settings: &PasswordHashingSettings,
 ) -> Result<(), anyhow::Error> {
+    let hashing_settings = hashing_settings.clone();
     let password_hash = spawn_blocking_with_tracing(
-        move || compute_password_hash(password)
+        move || compute_password_hash(password, &hashing_settings)
     )
     .await?
     .context("Failed to hash password")?;
@@ -123,17 +249,57 @@ pub async fn change_password(
     Ok(())
 }
 
-fn compute_password_hash(
-    password: Secret<String>
+pub fn compute_password_hash(
+    password: Secret<String>,
+    settings: &PasswordHashingSettings,
 ) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
+    let params = Params::new(
+        settings.memory_cost_kib,
+        settings.iterations,
+        settings.parallelism,
+        None,
+    )?;
     let password_hash = Argon2::new(
         Algorithm::Argon2id,
         Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap()
+        params,
     )
     .hash_password(password.expose_secret().as_bytes(), &salt)?
     .to_string();
 
     Ok(Secret::new(password_hash))    
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{dummy_password_hash, needs_rehash};
+    use crate::configuration::PasswordHashingSettings;
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn the_dummy_hash_follows_the_configured_cost() {
+        let settings = PasswordHashingSettings {
+            memory_cost_kib: 19456,
+            iterations: 3,
+            parallelism: 1,
+        };
+
+        let hash = dummy_password_hash(&settings).unwrap();
+
+        assert!(!needs_rehash(&hash, &settings));
+    }
+
+    #[test]
+    fn the_dummy_hash_is_only_computed_once_per_cost() {
+        let settings = PasswordHashingSettings {
+            memory_cost_kib: 8192,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        let first = dummy_password_hash(&settings).unwrap();
+        let second = dummy_password_hash(&settings).unwrap();
+
+        assert_eq!(first.expose_secret(), second.expose_secret());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::configuration::PasswordPolicySettings;
+use secrecy::{ExposeSecret, Secret};
+
+// One password per line, lowercase. The most common passwords come first,
+// then the variants of them long enough to pass `min_length`: doubled or
+// with the usual digits, years and symbols appended, and keyboard walks.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+//----------------------------------------------------------------
+/// Check `password` against every rule of the policy.
+/// Returns one message per failed rule, so they can all be shown at once.
+pub fn check_password_policy(
+    password: &Secret<String>,
+    username: &str,
+    policy: &PasswordPolicySettings,
+) -> Vec<String> {
+    let password = password.expose_secret();
+    let length = password.chars().count();
+    let mut violations = Vec::new();
+    if length < policy.min_length {
+        violations.push(format!(
+            "The new password must be at least {} characters long.",
+            policy.min_length
+        ));
+    }
+    if length > policy.max_length {
+        violations.push(format!(
+            "The new password must be at most {} characters long.",
+            policy.max_length
+        ));
+    }
+    let username = username.trim().to_lowercase();
+    if policy.reject_username
+        && !username.is_empty()
+        && password.to_lowercase().contains(&username)
+    {
+        violations.push("The new password must not contain your username.".into());
+    }
+    if policy.reject_common_passwords && is_common_password(password) {
+        violations.push("The new password is too common - please choose another one.".into());
+    }
+    if let Some(min_score) = policy.min_strength_score {
+        if strength_score(password) < min_score {
+            violations.push(
+                "The new password is too easy to guess - \
+                try a longer one or mix in other kinds of characters."
+                    .into(),
+            );
+        }
+    }
+    violations
+}
+
+fn is_common_password(password: &str) -> bool {
+    let password = password.to_lowercase();
+    COMMON_PASSWORDS.lines().any(|p| p == password)
+}
+
+/// A rough, zxcvbn-style estimate from 0 (trivial) to 4 (strong).
+/// It starts from the size of the character classes in use and discounts
+/// characters continuing a repetition or a sequence, e.g. `aaaa` or `1234`.
+pub fn strength_score(password: &str) -> u8 {
+    if is_common_password(password) {
+        return 0;
+    }
+    let chars: Vec<char> = password.chars().collect();
+    let mut pool_size = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool_size += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool_size += 26;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool_size += 10;
+    }
+    if chars.iter().any(|c| !c.is_ascii_alphanumeric()) {
+        pool_size += 33;
+    }
+    let effective_length: f64 = chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let predictable = i > 0 && (*c as i64 - chars[i - 1] as i64).abs() <= 1;
+            if predictable {
+                0.25
+            } else {
+                1.0
+            }
+        })
+        .sum();
+    let entropy_bits = effective_length * f64::from(pool_size.max(1)).log2();
+    match entropy_bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_password_policy, strength_score};
+    use crate::configuration::PasswordPolicySettings;
+    use secrecy::Secret;
+
+    fn policy() -> PasswordPolicySettings {
+        PasswordPolicySettings {
+            min_length: 12,
+            max_length: 64,
+            reject_username: true,
+            reject_common_passwords: true,
+            min_strength_score: Some(3),
+        }
+    }
+
+    fn violations(password: &str, username: &str) -> Vec<String> {
+        check_password_policy(&Secret::new(password.to_owned()), username, &policy())
+    }
+
+    #[test]
+    fn a_strong_password_passes_every_rule() {
+        assert!(violations("correct-Horse-battery-staple", "admin").is_empty());
+    }
+
+    #[test]
+    fn a_short_password_is_rejected() {
+        assert!(violations("x7#Qp!", "admin")
+            .contains(&"The new password must be at least 12 characters long.".to_owned()));
+    }
+
+    #[test]
+    fn a_long_password_is_rejected() {
+        let password = "x7#Qp!".repeat(11);
+        assert!(violations(&password, "admin")
+            .contains(&"The new password must be at most 64 characters long.".to_owned()));
+    }
+
+    #[test]
+    fn a_password_containing_the_username_is_rejected() {
+        assert!(violations("my-Sang.Khuu-is-great", "sang.khuu")
+            .contains(&"The new password must not contain your username.".to_owned()));
+    }
+
+    #[test]
+    fn a_common_password_is_rejected_whatever_its_case() {
+        let violations = violations("PasswordPassword", "admin");
+        assert!(violations
+            .contains(&"The new password is too common - please choose another one.".to_owned()));
+    }
+
+    #[test]
+    fn a_common_password_long_enough_for_the_length_rule_is_rejected() {
+        let violations = violations("Sunshine2025", "admin");
+        assert!(!violations
+            .contains(&"The new password must be at least 12 characters long.".to_owned()));
+        assert!(violations
+            .contains(&"The new password is too common - please choose another one.".to_owned()));
+    }
+
+    #[test]
+    fn every_failed_rule_is_reported() {
+        assert_eq!(violations("admin123", "admin").len(), 4);
+    }
+
+    #[test]
+    fn the_strength_score_is_disabled_when_unset() {
+        let policy = PasswordPolicySettings {
+            min_strength_score: None,
+            ..policy()
+        };
+        let password = Secret::new("aaaaaaaaaaaaaaaa".to_owned());
+        assert!(check_password_policy(&password, "admin", &policy).is_empty());
+    }
+
+    #[test]
+    fn repetitions_and_sequences_lower_the_strength_score() {
+        assert_eq!(strength_score("password"), 0);
+        assert_eq!(strength_score("aaaaaaaaaaaaaaaa"), 0);
+        assert!(strength_score("abcdefghijklmnop") < strength_score("qzmxnwbcjvkelrtu"));
+        assert_eq!(strength_score("7f3c1e8a-90b2-4d6f-a1c3-5e7b9d2f4a6c"), 4);
+    }
+}
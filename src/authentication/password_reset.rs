@@ -0,0 +1,170 @@
+use crate::authentication::compute_password_hash;
+use crate::configuration::PasswordHashingSettings;
+use crate::user_sessions::revoke_other_user_sessions;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Reset links are only valid for a short while.
+const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+//----------------------------------------------------------------
+/// An active user a password reset link can be sent to.
+pub struct ResetRecipient {
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+#[tracing::instrument(name = "Get password reset recipient", skip(db_pool))]
+pub async fn get_reset_recipient(
+    db_pool: &PgPool,
+    email: &str,
+) -> Result<Option<ResetRecipient>, sqlx::Error> {
+    sqlx::query_as!(
+        ResetRecipient,
+        r#"
+        SELECT user_id, email AS "email!"
+        FROM users
+        WHERE lower(email) = lower($1) AND is_active
+        "#,
+        email
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Store a new reset token and return it in clear text.
+/// Only its hash is persisted, so a database leak does not hand out
+/// working reset links.
+#[tracing::instrument(name = "Create password reset token", skip(db_pool))]
+pub async fn create_reset_token(db_pool: &PgPool, user_id: Uuid) -> Result<Secret<String>, sqlx::Error> {
+    let token = generate_reset_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        hash_reset_token(&token),
+        user_id,
+        Utc::now() + Duration::minutes(RESET_TOKEN_TTL_MINUTES),
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(Secret::new(token))
+}
+
+/// Returns `true` if `token` can still be used to reset a password.
+#[tracing::instrument(name = "Check password reset token", skip_all)]
+pub async fn is_valid_reset_token(db_pool: &PgPool, token: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// The username of the account `token` can reset, if it is still usable.
+#[tracing::instrument(name = "Get password reset token username", skip_all)]
+pub async fn get_reset_token_username(
+    db_pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u.username
+        FROM password_reset_tokens t
+        JOIN users u ON u.user_id = t.user_id
+        WHERE t.token_hash = $1 AND t.used_at IS NULL AND t.expires_at > now()
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(row.map(|r| r.username))
+}
+
+/// Consume `token`, store the new password and invalidate every session
+/// of the user. Returns `None` if the token is invalid, used or expired.
+#[tracing::instrument(name = "Reset password", skip_all)]
+pub async fn reset_password(
+    db_pool: &PgPool,
+    token: &str,
+    password: Secret<String>,
+    hashing_settings: &PasswordHashingSettings,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let hashing_settings = hashing_settings.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing_settings))
+            .await?
+            .context("Failed to hash password")?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens SET used_at = now()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to consume the password reset token.")?;
+    let user_id = match row {
+        Some(r) => r.user_id,
+        None => return Ok(None),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $2, sessions_invalidated_at = now()
+        WHERE user_id = $1
+        "#,
+        user_id,
+        password_hash.expose_secret(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the new password.")?;
+    // Any other link sent to this user is now pointless
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1 AND used_at IS NULL"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete outstanding password reset tokens.")?;
+    revoke_other_user_sessions(&mut transaction, user_id, None).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password.")?;
+    Ok(Some(user_id))
+}
+
+fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a random 32-characters-long case-sensitive reset token.
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
@@ -0,0 +1,161 @@
+use crate::authentication::Role;
+use crate::utils::json_error;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpResponse};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+//----------------------------------------------------------------
+/// Actions that are restricted to some roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    PublishIssue,
+    ManageSubscribers,
+    ManageUsers,
+    // Endpoints receive subscriber data, only owners decide where it goes.
+    ManageWebhooks,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::PublishIssue => "publish_issue",
+            Permission::ManageSubscribers => "manage_subscribers",
+            Permission::ManageUsers => "manage_users",
+            Permission::ManageWebhooks => "manage_webhooks",
+        }
+    }
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Owner => &[
+                Permission::PublishIssue,
+                Permission::ManageSubscribers,
+                Permission::ManageUsers,
+                Permission::ManageWebhooks,
+            ],
+            Role::Editor => &[Permission::PublishIssue, Permission::ManageSubscribers],
+            Role::Viewer => &[],
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+//----------------------------------------------------------------
+/// Middleware rejecting requests from users whose role lacks `permission`
+/// with a 403 page.
+/// It relies on the `Role` inserted by `reject_anonymous_users` or
+/// `reject_invalid_api_tokens`, so it must be registered on a resource or
+/// scope nested inside the `/admin` or `/api/v1` scope.
+#[derive(Clone, Copy)]
+pub struct RequirePermission {
+    permission: Permission,
+    json: bool,
+}
+
+impl RequirePermission {
+    pub fn new(permission: Permission) -> Self {
+        Self {
+            permission,
+            json: false,
+        }
+    }
+
+    /// Answer with a JSON error rather than an HTML page, for the API.
+    pub fn json(self) -> Self {
+        Self { json: true, ..self }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequirePermissionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service,
+            permission: self.permission,
+            json: self.json,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: S,
+    permission: Permission,
+    json: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let role = req.extensions().get::<Role>().copied();
+        match role {
+            Some(role) if role.has_permission(self.permission) => {
+                let response = self.service.call(req);
+                Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+            }
+            _ => {
+                tracing::warn!(
+                    permission = self.permission.as_str(),
+                    role = role.map(|r| r.as_str()),
+                    "Rejected a request lacking the required permission.",
+                );
+                let response = if self.json {
+                    json_error(
+                        StatusCode::FORBIDDEN,
+                        "You do not have permission to perform this action.",
+                    )
+                } else {
+                    forbidden_page()
+                };
+                let response = req.into_response(response).map_into_right_body();
+                Box::pin(async move { Ok(response) })
+            }
+        }
+    }
+}
+
+fn forbidden_page() -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>You do not have permission to perform this action.</p>
+    <p>Ask an owner of this newsletter to change your role.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )
+}
@@ -0,0 +1,316 @@
+use crate::configuration::LoginThrottleSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+use uuid::Uuid;
+
+// Failures tolerated before answers start slowing down.
+const FREE_FAILURES: u64 = 3;
+
+//----------------------------------------------------------------
+/// What a lockout applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutScope {
+    Username,
+    IpAddress,
+    // Wrong TOTP or recovery codes, counted per user
+    SecondFactor,
+    // Password reset requests, counted per email address
+    PasswordReset,
+}
+
+impl LockoutScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutScope::Username => "username",
+            LockoutScope::IpAddress => "ip_address",
+            LockoutScope::SecondFactor => "second_factor",
+            LockoutScope::PasswordReset => "password_reset",
+        }
+    }
+}
+
+pub enum LoginThrottleDecision {
+    Allowed { delay: Duration },
+    LockedOut { retry_after: Duration },
+}
+
+/// Tracks failed login attempts per username and per client IP in Redis,
+/// wrong second factors per user and password reset requests per address. Counters expire on their own after
+/// `failure_window_seconds`, lockouts after `lockout_seconds`.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    connection: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI.")?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self {
+            connection,
+            settings,
+        })
+    }
+
+    #[tracing::instrument(name = "Check login throttle", skip(self))]
+    pub async fn check(
+        &self,
+        username: &str,
+        ip_address: Option<&str>,
+    ) -> Result<LoginThrottleDecision, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        for (scope, value) in subjects(username, ip_address) {
+            if let Some(retry_after) = self.lockout(&mut connection, scope, &value).await? {
+                return Ok(LoginThrottleDecision::LockedOut { retry_after });
+            }
+        }
+        let failures: Option<u64> = connection
+            .get(self.key("failures", LockoutScope::Username, &username.to_lowercase()))
+            .await
+            .context("Failed to read failed login attempts from Redis.")?;
+        Ok(LoginThrottleDecision::Allowed {
+            delay: progressive_delay(
+                failures.unwrap_or(0),
+                self.settings.base_delay_milliseconds,
+                self.settings.max_delay_milliseconds,
+            ),
+        })
+    }
+
+    /// Count a failed attempt. Returns the scopes that just got locked out.
+    #[tracing::instrument(name = "Record failed login attempt", skip(self))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        ip_address: Option<&str>,
+    ) -> Result<Vec<LockoutScope>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut lockouts = Vec::new();
+        for (scope, value) in subjects(username, ip_address) {
+            let max_failures = match scope {
+                LockoutScope::Username => self.settings.max_failures_per_username,
+                LockoutScope::IpAddress => self.settings.max_failures_per_ip,
+                LockoutScope::SecondFactor => self.settings.max_second_factor_failures,
+                LockoutScope::PasswordReset => self.settings.max_password_resets_per_email,
+            };
+            if self
+                .count_failure(&mut connection, scope, &value, max_failures)
+                .await?
+            {
+                lockouts.push(scope);
+            }
+        }
+        Ok(lockouts)
+    }
+
+    /// Forget the failures of a user who just logged in.
+    #[tracing::instrument(name = "Reset failed login attempts", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        connection
+            .del::<_, ()>(self.key("failures", LockoutScope::Username, &username.to_lowercase()))
+            .await
+            .context("Failed to reset failed login attempts in Redis.")?;
+        Ok(())
+    }
+
+    /// How long `user_id` must wait before entering a second factor again,
+    /// if they are locked out.
+    #[tracing::instrument(name = "Check second factor throttle", skip(self))]
+    pub async fn check_second_factor(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        self.lockout(
+            &mut connection,
+            LockoutScope::SecondFactor,
+            &user_id.to_string(),
+        )
+        .await
+    }
+
+    /// Count a wrong second factor. Unlike the session, the counter survives
+    /// logging in again. Returns `true` if the user just got locked out.
+    #[tracing::instrument(name = "Record failed second factor", skip(self))]
+    pub async fn record_second_factor_failure(&self, user_id: Uuid) -> Result<bool, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        self.count_failure(
+            &mut connection,
+            LockoutScope::SecondFactor,
+            &user_id.to_string(),
+            self.settings.max_second_factor_failures,
+        )
+        .await
+    }
+
+    #[tracing::instrument(name = "Reset failed second factors", skip(self))]
+    pub async fn record_second_factor_success(&self, user_id: Uuid) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        connection
+            .del::<_, ()>(self.key("failures", LockoutScope::SecondFactor, &user_id.to_string()))
+            .await
+            .context("Failed to reset failed second factors in Redis.")?;
+        Ok(())
+    }
+
+    /// Count a password reset request. They are limited per address, so an
+    /// inbox cannot be flooded, and share the per IP counter of failed
+    /// logins. Returns how long to wait if the request must be turned away.
+    #[tracing::instrument(name = "Record password reset request", skip(self))]
+    pub async fn record_password_reset_request(
+        &self,
+        email: &str,
+        ip_address: Option<&str>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut subjects = vec![(
+            LockoutScope::PasswordReset,
+            email.to_lowercase(),
+            self.settings.max_password_resets_per_email,
+        )];
+        if let Some(ip_address) = ip_address {
+            subjects.push((
+                LockoutScope::IpAddress,
+                ip_address.to_owned(),
+                self.settings.max_failures_per_ip,
+            ));
+        }
+        for (scope, value, _) in &subjects {
+            if let Some(retry_after) = self.lockout(&mut connection, *scope, value).await? {
+                return Ok(Some(retry_after));
+            }
+        }
+        for (scope, value, max_requests) in &subjects {
+            self.count_failure(&mut connection, *scope, value, *max_requests)
+                .await?;
+        }
+        Ok(None)
+    }
+
+    pub fn lockout_seconds(&self) -> u64 {
+        self.settings.lockout_seconds
+    }
+
+    async fn lockout(
+        &self,
+        connection: &mut ConnectionManager,
+        scope: LockoutScope,
+        value: &str,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        // -2 means the key does not exist, -1 that it has no expiry
+        let ttl: i64 = connection
+            .ttl(self.key("lockout", scope, value))
+            .await
+            .context("Failed to read a lockout from Redis.")?;
+        if ttl == -2 {
+            return Ok(None);
+        }
+        let retry_after = u64::try_from(ttl).unwrap_or(self.settings.lockout_seconds);
+        Ok(Some(Duration::from_secs(retry_after)))
+    }
+
+    /// Returns `true` if this failure locks `value` out.
+    async fn count_failure(
+        &self,
+        connection: &mut ConnectionManager,
+        scope: LockoutScope,
+        value: &str,
+        max_failures: u64,
+    ) -> Result<bool, anyhow::Error> {
+        let failures_key = self.key("failures", scope, value);
+        let failures: u64 = connection
+            .incr(&failures_key, 1)
+            .await
+            .context("Failed to count a failed login attempt in Redis.")?;
+        if failures == 1 {
+            let _: () = redis::cmd("EXPIRE")
+                .arg(&failures_key)
+                .arg(self.settings.failure_window_seconds)
+                .query_async(connection)
+                .await
+                .context("Failed to set the expiry of a failure counter in Redis.")?;
+        }
+        if failures < max_failures {
+            return Ok(false);
+        }
+        let _: () = redis::cmd("SET")
+            .arg(self.key("lockout", scope, value))
+            .arg(1)
+            .arg("EX")
+            .arg(self.settings.lockout_seconds)
+            .query_async(connection)
+            .await
+            .context("Failed to store a lockout in Redis.")?;
+        // Start from a clean slate once the lockout is over
+        connection
+            .del::<_, ()>(&failures_key)
+            .await
+            .context("Failed to reset a failure counter in Redis.")?;
+        Ok(true)
+    }
+
+    fn key(&self, kind: &str, scope: LockoutScope, value: &str) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.settings.key_prefix,
+            kind,
+            scope.as_str(),
+            value
+        )
+    }
+}
+
+fn subjects(username: &str, ip_address: Option<&str>) -> Vec<(LockoutScope, String)> {
+    let mut subjects = vec![(LockoutScope::Username, username.to_lowercase())];
+    if let Some(ip_address) = ip_address {
+        subjects.push((LockoutScope::IpAddress, ip_address.to_owned()));
+    }
+    subjects
+}
+
+/// No delay for the first few failures, then doubling up to `max_delay_ms`.
+pub fn progressive_delay(failures: u64, base_delay_ms: u64, max_delay_ms: u64) -> Duration {
+    if failures < FREE_FAILURES {
+        return Duration::ZERO;
+    }
+    let exponent = (failures - FREE_FAILURES).min(32) as u32;
+    let delay_ms = base_delay_ms.saturating_mul(2u64.saturating_pow(exponent));
+    Duration::from_millis(delay_ms.min(max_delay_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::progressive_delay;
+    use std::time::Duration;
+
+    #[test]
+    fn the_first_failures_are_not_delayed() {
+        for failures in 0..3 {
+            assert_eq!(progressive_delay(failures, 250, 4000), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn the_delay_doubles_with_every_further_failure() {
+        assert_eq!(progressive_delay(3, 250, 4000), Duration::from_millis(250));
+        assert_eq!(progressive_delay(4, 250, 4000), Duration::from_millis(500));
+        assert_eq!(progressive_delay(5, 250, 4000), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn the_delay_is_capped() {
+        assert_eq!(progressive_delay(7, 250, 4000), Duration::from_millis(4000));
+        assert_eq!(progressive_delay(u64::MAX, 250, 4000), Duration::from_millis(4000));
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+// RFC 6238 defaults, which is what authenticator apps expect.
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+// Accept codes from the previous and next time step to absorb clock drift.
+const TOTP_ALLOWED_DRIFT_STEPS: u64 = 1;
+const ISSUER: &str = "Newsletter";
+const RECOVERY_CODES_COUNT: usize = 10;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+//----------------------------------------------------------------
+/// Generate a random 160-bit shared secret, base32-encoded.
+pub fn generate_totp_secret() -> Secret<String> {
+    let mut key = [0u8; 20];
+    thread_rng().fill_bytes(&mut key);
+    Secret::new(base32_encode(&key))
+}
+
+/// The `otpauth://` URI authenticator apps read from a QR code.
+pub fn provisioning_uri(secret: &Secret<String>, account_name: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}\
+        &algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP_SECONDS}",
+        issuer = urlencoding::encode(ISSUER),
+        account = urlencoding::encode(account_name),
+        secret = secret.expose_secret(),
+    )
+}
+
+/// `uri` as an inline SVG QR code, for authenticator apps to scan.
+pub fn provisioning_qr_code(uri: &str) -> Result<String, anyhow::Error> {
+    let qr_code = QrCode::new(uri.as_bytes()).context("Failed to encode the URI as a QR code.")?;
+    let svg = qr_code
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    // The XML declaration has no place inside an HTML page
+    Ok(svg
+        .find("<svg")
+        .map(|start| svg[start..].to_owned())
+        .unwrap_or(svg))
+}
+
+/// Returns the time step `code` is valid for, if any.
+pub fn verify_totp_code(secret: &Secret<String>, code: &str, unix_time: u64) -> Option<u64> {
+    let key = base32_decode(secret.expose_secret())?;
+    let code: u32 = code.trim().parse().ok()?;
+    let current_step = unix_time / TOTP_STEP_SECONDS;
+    (current_step.saturating_sub(TOTP_ALLOWED_DRIFT_STEPS)
+        ..=current_step + TOTP_ALLOWED_DRIFT_STEPS)
+        .find(|step| hotp(&key, *step, TOTP_DIGITS) == code)
+}
+
+/// The code an authenticator app displays at `unix_time`.
+pub fn generate_totp_code(secret: &Secret<String>, unix_time: u64) -> Option<String> {
+    let key = base32_decode(secret.expose_secret())?;
+    let code = hotp(&key, unix_time / TOTP_STEP_SECONDS, TOTP_DIGITS);
+    Some(format!("{:0width$}", code, width = TOTP_DIGITS as usize))
+}
+
+/// Check a code against the current time, e.g. while enrolling.
+pub fn is_valid_totp_code(secret: &Secret<String>, code: &str) -> bool {
+    verify_totp_code(secret, code, current_unix_time()).is_some()
+}
+
+// HOTP as defined in RFC 4226.
+fn hotp(key: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+// Unpadded RFC 4648 base32.
+fn base32_encode(data: &[u8]) -> String {
+    let mut output = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            output.push(BASE32_ALPHABET[((buffer >> (bits - 5)) & 0x1f) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.trim_end_matches('=').chars() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            output.push((buffer >> (bits - 8)) as u8);
+            bits -= 8;
+        }
+    }
+    Some(output)
+}
+
+fn current_unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the Unix epoch")
+        .as_secs()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+/// Generate a batch of one-time recovery codes, e.g. `k3x9q-0pz7m`.
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(10)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+//----------------------------------------------------------------
+#[tracing::instrument(name = "Get TOTP secret", skip(db_pool))]
+pub async fn get_totp_secret(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    Ok(row.totp_secret.map(Secret::new))
+}
+
+/// Store a confirmed secret and return freshly generated recovery codes.
+/// They are only stored hashed, so this is the one chance to show them.
+#[tracing::instrument(name = "Enable TOTP", skip(db_pool, secret))]
+pub async fn enable_totp(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    secret: &Secret<String>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes.iter().map(|c| hash_recovery_code(c)).collect();
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = $2, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id,
+        secret.expose_secret(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete old recovery codes.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::text[])
+        "#,
+        user_id,
+        &code_hashes,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable TOTP.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable TOTP", skip(db_pool))]
+pub async fn disable_totp(db_pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = NULL, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the TOTP secret.")?;
+    sqlx::query!(
+        r#"DELETE FROM totp_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable TOTP.")?;
+    Ok(())
+}
+
+/// Check either a TOTP code or an unused recovery code.
+/// Accepted codes cannot be used a second time.
+#[tracing::instrument(name = "Verify second factor", skip(db_pool, secret, code))]
+pub async fn verify_second_factor(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    secret: &Secret<String>,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    if let Some(step) = verify_totp_code(secret, code, current_unix_time()) {
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE users SET totp_last_used_step = $2
+            WHERE user_id = $1
+            AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step as i64,
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to record the TOTP time step.")?
+        .rows_affected();
+        return Ok(n_updated_rows > 0);
+    }
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to consume the recovery code.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32_decode, base32_encode, hotp, verify_totp_code};
+    use claim::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+
+    // The SHA1 seed from RFC 6238, appendix B.
+    const RFC_SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_matches_the_rfc_6238_test_vectors() {
+        let cases = [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+        ];
+        for (unix_time, expected) in cases {
+            assert_eq!(hotp(RFC_SEED, unix_time / 30, 8), expected);
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        let encoded = base32_encode(RFC_SEED);
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode(&encoded).unwrap(), RFC_SEED);
+    }
+
+    #[test]
+    fn a_code_from_the_current_or_adjacent_step_is_accepted() {
+        let secret = Secret::new(base32_encode(RFC_SEED));
+        // 1111111109 is the last second of step 37037036
+        assert_some_eq!(verify_totp_code(&secret, "081804", 1111111109), 37037036);
+        assert_some_eq!(verify_totp_code(&secret, "081804", 1111111109 + 30), 37037036);
+    }
+
+    #[test]
+    fn a_code_outside_the_drift_window_is_rejected() {
+        let secret = Secret::new(base32_encode(RFC_SEED));
+        assert_none!(verify_totp_code(&secret, "081804", 1111111109 + 90));
+        assert_none!(verify_totp_code(&secret, "not-a-code", 1111111109));
+    }
+}
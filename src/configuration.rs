@@ -1,8 +1,11 @@
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::{postgres::{PgConnectOptions, PgSslMode}, ConnectOptions};
 use std::convert::{TryFrom, TryInto};
+use std::net::IpAddr;
 use crate::email_client::EmailClient;
 
 
@@ -13,6 +16,15 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub session: SessionSettings,
+    pub idempotency: IdempotencySettings,
+    pub metrics: MetricsSettings,
+    // Traces are only exported when an OTLP collector is configured.
+    pub otlp: Option<OtlpSettings>,
 }
 //------------------------------------------------------------------------------
 
@@ -26,7 +38,7 @@ pub struct EmailClientSettings {
 
 impl EmailClientSettings {
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
-        SubscriberEmail::parse(self.sender_email.clone())
+        SubscriberEmail::parse(self.sender_email.clone()).map_err(|e| e.to_string())
     }
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
@@ -43,6 +55,128 @@ impl EmailClientSettings {
     }
 }
 //------------------------------------------------------------------------------
+/// Basic auth credentials Postmark must present when calling our webhooks.
+#[derive(Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// Prometheus must send this token to scrape `/metrics`.
+#[derive(Deserialize, Clone)]
+pub struct MetricsSettings {
+    pub bearer_token: Secret<String>,
+}
+
+/// Where to export traces, over OTLP/gRPC.
+#[derive(Deserialize, Clone)]
+pub struct OtlpSettings {
+    // e.g. http://localhost:4317
+    pub endpoint: String,
+}
+//------------------------------------------------------------------------------
+/// Limits on failed login attempts, tracked in Redis.
+#[derive(Deserialize, Clone)]
+pub struct LoginThrottleSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u64,
+    // Wrong codes tolerated between a correct password and a correct code.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_second_factor_failures: u64,
+    // Reset emails an address can ask for within the failure window.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_password_resets_per_email: u64,
+    // Failures older than this are forgotten.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub failure_window_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: u64,
+    // Delay before answering, doubled for every failure past the third.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub base_delay_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_delay_milliseconds: u64,
+    // Namespace for our keys in a Redis instance shared with the sessions.
+    pub key_prefix: String,
+}
+//------------------------------------------------------------------------------
+/// How long admin sessions stay valid.
+#[derive(Deserialize, Clone)]
+pub struct SessionSettings {
+    // Inactivity after which users have to log in again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_minutes: u64,
+    // Lifetime of a session, however active it is.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub absolute_timeout_hours: u64,
+}
+
+impl SessionSettings {
+    pub fn idle_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.idle_timeout_minutes * 60)
+    }
+    pub fn absolute_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.absolute_timeout_hours * 60 * 60)
+    }
+}
+//------------------------------------------------------------------------------
+/// How long saved responses are replayed, and how they are cleaned up after.
+#[derive(Deserialize, Clone)]
+pub struct IdempotencySettings {
+    // Retries with the same key after that are processed as new requests.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_hours: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_minutes: u64,
+    // Keeps every DELETE short, whatever the backlog of expired keys.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_batch_size: i64,
+    // How long a retry waits for the original request before giving up
+    // with a 409.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub in_flight_wait_seconds: u64,
+}
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retention_hours * 60 * 60)
+    }
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_minutes * 60)
+    }
+    pub fn in_flight_wait(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.in_flight_wait_seconds)
+    }
+}
+//------------------------------------------------------------------------------
+/// Argon2id cost parameters for new password hashes.
+/// Hashes computed with other values are upgraded on the next login.
+#[derive(Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub memory_cost_kib: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub iterations: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub parallelism: u32,
+}
+//------------------------------------------------------------------------------
+/// Rules new passwords must follow when they are set or changed.
+#[derive(Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_length: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_length: usize,
+    pub reject_username: bool,
+    pub reject_common_passwords: bool,
+    // Minimum estimated strength from 0 (trivial) to 4 (strong), if any.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub min_strength_score: Option<u8>,
+}
+//------------------------------------------------------------------------------
 #[derive(Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -50,6 +184,16 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // Bumped whenever the consent wording on our subscription forms changes.
+    pub consent_text_version: String,
+    // Sites allowed to call `POST /subscriptions` from the browser,
+    // e.g. `https://www.example.com` for the marketing site.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    // Reverse proxies whose `X-Forwarded-For` header we believe, see
+    // `utils::client_ip`. The header is ignored when it comes from anyone else.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(Deserialize, Clone)]
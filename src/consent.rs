@@ -0,0 +1,118 @@
+use crate::utils::client_ip;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+//----------------------------------------------------------------
+// Wrapper type to retrieve the version of the consent wording shown
+// on our subscription forms from the application context.
+pub struct ConsentTextVersion(pub String);
+
+pub enum ConsentEventType {
+    Subscribed,
+    Confirmed,
+}
+
+impl ConsentEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventType::Subscribed => "subscribed",
+            ConsentEventType::Confirmed => "confirmed",
+        }
+    }
+}
+
+/// How and from where a subscriber gave their consent.
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_text_version: String,
+}
+
+impl ConsentContext {
+    pub fn from_request(request: &HttpRequest, source: String, consent_text_version: &str) -> Self {
+        let ip_address = client_ip(request);
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_owned);
+        Self {
+            ip_address,
+            user_agent,
+            source,
+            consent_text_version: consent_text_version.to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ConsentEvent {
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_text_version: String,
+}
+//----------------------------------------------------------------
+#[tracing::instrument(
+    name = "Record consent event",
+    skip(executor, context),
+    fields(event_type = %event_type.as_str())
+)]
+pub async fn record_consent_event(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    event_type: ConsentEventType,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            id,
+            subscriber_id,
+            event_type,
+            occurred_at,
+            ip_address,
+            user_agent,
+            source,
+            consent_text_version
+        )
+        VALUES ($1, $2, $3, now(), $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event_type.as_str(),
+        context.ip_address,
+        context.user_agent,
+        context.source,
+        context.consent_text_version,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get consent events", skip(db_pool))]
+pub async fn get_consent_events(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT event_type, occurred_at, ip_address, user_agent, source, consent_text_version
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(db_pool)
+    .await
+}
@@ -0,0 +1,295 @@
+use crate::suppression::SuppressedEmail;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+//----------------------------------------------------------------
+/// Everything we store about a single email address, as returned to
+/// the data subject when they exercise their right of access.
+#[derive(Serialize)]
+pub struct DataSubjectDossier {
+    pub email: String,
+    pub generated_at: DateTime<Utc>,
+    pub subscriptions: Vec<SubscriptionRecord>,
+    pub subscription_tokens: Vec<SubscriptionTokenRecord>,
+    pub queued_deliveries: Vec<QueuedDeliveryRecord>,
+    pub consent_events: Vec<ConsentEventRecord>,
+    pub topic_opt_outs: Vec<TopicOptOutRecord>,
+    pub bounce_events: Vec<BounceEventRecord>,
+    pub webhook_events: Vec<WebhookEventRecord>,
+    pub suppression: Option<SuppressedEmail>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub status: String,
+    pub delivery_frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriptionTokenRecord {
+    pub subscription_token: String,
+    pub subscriber_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct QueuedDeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: String,
+}
+
+#[derive(Serialize)]
+pub struct ConsentEventRecord {
+    pub subscriber_id: Uuid,
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_text_version: String,
+}
+
+#[derive(Serialize)]
+pub struct TopicOptOutRecord {
+    pub subscriber_id: Uuid,
+    pub topic: String,
+}
+
+#[derive(Serialize)]
+pub struct BounceEventRecord {
+    pub record_type: String,
+    pub bounce_type: Option<String>,
+    pub description: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookEventRecord {
+    pub event_type: String,
+    pub payload: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// How many rows an erasure removed, per table.
+#[derive(Debug, Default, Serialize, utoipa::ToSchema)]
+pub struct ErasureReport {
+    pub subscriptions: u64,
+    pub queued_deliveries: u64,
+    pub bounce_events: u64,
+    pub webhook_events: u64,
+}
+
+impl ErasureReport {
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions == 0
+            && self.queued_deliveries == 0
+            && self.bounce_events == 0
+            && self.webhook_events == 0
+    }
+}
+//----------------------------------------------------------------
+#[tracing::instrument(name = "Collect data subject dossier", skip(db_pool))]
+pub async fn get_dossier(db_pool: &PgPool, email: &str) -> Result<DataSubjectDossier, anyhow::Error> {
+    let subscriptions = sqlx::query_as!(
+        SubscriptionRecord,
+        r#"
+        SELECT id, email, name, subscribed_at, status, delivery_frequency, paused_until
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve subscriptions.")?;
+
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+        SELECT t.subscription_token, t.subscriber_id
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve subscription tokens.")?;
+
+    let queued_deliveries = sqlx::query_as!(
+        QueuedDeliveryRecord,
+        r#"
+        SELECT q.newsletter_issue_id, i.title, i.published_at
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE lower(q.subscriber_email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve queued deliveries.")?;
+
+    let consent_events = sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+        SELECT
+            c.subscriber_id, c.event_type, c.occurred_at, c.ip_address,
+            c.user_agent, c.source, c.consent_text_version
+        FROM consent_events c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY c.occurred_at
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve consent events.")?;
+
+    let topic_opt_outs = sqlx::query_as!(
+        TopicOptOutRecord,
+        r#"
+        SELECT o.subscriber_id, t.name AS topic
+        FROM topic_opt_outs o
+        JOIN topics t ON t.topic_id = o.topic_id
+        JOIN subscriptions s ON s.id = o.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY t.name
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve topic opt-outs.")?;
+
+    let bounce_events = sqlx::query_as!(
+        BounceEventRecord,
+        r#"
+        SELECT record_type, bounce_type, description, received_at
+        FROM bounce_events
+        WHERE lower(email) = lower($1)
+        ORDER BY received_at
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve bounce events.")?;
+
+    // Events about a subscriber carry their email in the payload
+    let webhook_events = sqlx::query_as!(
+        WebhookEventRecord,
+        r#"
+        SELECT event_type, payload, occurred_at
+        FROM webhook_events
+        WHERE lower(payload::jsonb ->> 'email') = lower($1)
+        ORDER BY occurred_at
+        "#,
+        email
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve webhook events.")?;
+
+    let suppression = sqlx::query_as!(
+        SuppressedEmail,
+        r#"
+        SELECT email, source, reason, created_at
+        FROM suppressed_emails
+        WHERE email = lower($1)
+        "#,
+        email
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve suppression entry.")?;
+
+    Ok(DataSubjectDossier {
+        email: email.to_owned(),
+        generated_at: Utc::now(),
+        subscriptions,
+        subscription_tokens,
+        queued_deliveries,
+        consent_events,
+        topic_opt_outs,
+        bounce_events,
+        webhook_events,
+        suppression,
+    })
+}
+
+/// Hard-delete every row tied to `email`.
+/// The suppression list entry, if any, is kept on purpose: it is what
+/// guarantees we never mail the address again.
+/// Subscription tokens, consent events and topic opt-outs go with their subscription
+/// thanks to `ON DELETE CASCADE`, the deliveries of webhook events with their event.
+#[tracing::instrument(name = "Erase data subject", skip(transaction))]
+pub async fn erase_data_subject(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<ErasureReport, anyhow::Error> {
+    let queued_deliveries = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = lower($1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete queued deliveries.")?
+    .rows_affected();
+
+    let subscriptions = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscriptions.")?
+    .rows_affected();
+
+    let bounce_events = sqlx::query!(
+        r#"
+        DELETE FROM bounce_events
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete bounce events.")?
+    .rows_affected();
+
+    let webhook_events = sqlx::query!(
+        r#"
+        DELETE FROM webhook_events
+        WHERE lower(payload::jsonb ->> 'email') = lower($1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete webhook events.")?
+    .rows_affected();
+
+    Ok(ErasureReport {
+        subscriptions,
+        queued_deliveries,
+        bounce_events,
+        webhook_events,
+    })
+}
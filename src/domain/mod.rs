@@ -2,6 +2,6 @@ mod subscriber_email;
 mod subscriber_name;
 mod new_subscriber;
 
-pub use subscriber_name::SubscriberName;
-pub use subscriber_email::SubscriberEmail;
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
+pub use subscriber_email::{SubscriberEmail, SubscriberEmailError};
 pub use new_subscriber::NewSubscriber;
\ No newline at end of file
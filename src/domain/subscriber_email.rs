@@ -3,12 +3,31 @@ use validator::validate_email;
 #[derive(Debug,Clone)]
 pub struct SubscriberEmail(String);
 
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriberEmailError {
+    #[error("The email address cannot be empty.")]
+    Empty,
+    #[error("The email address is not valid.")]
+    InvalidFormat,
+}
+
+impl SubscriberEmailError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberEmailError::Empty => "empty",
+            SubscriberEmailError::InvalidFormat => "invalid_format",
+        }
+    }
+}
+
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
+    pub fn parse(s: String) -> Result<Self, SubscriberEmailError> {
+        if s.trim().is_empty() {
+            Err(SubscriberEmailError::Empty)
+        } else if validate_email(&s) {
             Ok(Self(s))
         } else {
-            Err(format!("{} is not a valid email", s))
+            Err(SubscriberEmailError::InvalidFormat)
         }
     }
 }
@@ -3,15 +3,42 @@ use unicode_segmentation::UnicodeSegmentation;
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
+/// Why a name was rejected, each rule has its own `code` so that
+/// API clients can tell them apart.
+/// The messages do not repeat the name, they end up in HTML pages.
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriberNameError {
+    #[error("The name cannot be empty.")]
+    Empty,
+    #[error("The name must be at most 256 characters.")]
+    TooLong,
+    #[error("The name cannot contain any of / ( ) \" < > \\ {{ }}")]
+    ForbiddenCharacters,
+}
+
+impl SubscriberNameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SubscriberNameError::Empty => "empty",
+            SubscriberNameError::TooLong => "too_long",
+            SubscriberNameError::ForbiddenCharacters => "forbidden_characters",
+        }
+    }
+}
+
 impl SubscriberName {
-    pub fn parse(s: String) -> Result<Self, String> {
+    pub fn parse(s: String) -> Result<Self, SubscriberNameError> {
         let is_empty_or_whitespace = s.trim().is_empty();
         let is_too_long = s.graphemes(true).count() > 256;
         let forbidden_chars = ['/','(',')','"','\\','<','>','{','}'];
         let contains_forbidden_chars = s.chars().any(|g| forbidden_chars.contains(&g));
 
-        if is_empty_or_whitespace || contains_forbidden_chars ||is_too_long {
-            Err(format!("{} is not valid name", s))
+        if is_empty_or_whitespace {
+            Err(SubscriberNameError::Empty)
+        } else if is_too_long {
+            Err(SubscriberNameError::TooLong)
+        } else if contains_forbidden_chars {
+            Err(SubscriberNameError::ForbiddenCharacters)
         } else {
             Ok(Self(s))
         }
@@ -61,6 +88,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn each_rejection_reports_its_own_code() {
+        let code = |name: &str| SubscriberName::parse(name.to_string()).unwrap_err().code();
+        assert_eq!(code(" "), "empty");
+        assert_eq!(code(&"a".repeat(257)), "too_long");
+        assert_eq!(code("<script>"), "forbidden_characters");
+    }
+
+    #[test]
+    fn rejections_do_not_repeat_the_name() {
+        let error = SubscriberName::parse("<script>".to_string()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"The name cannot contain any of / ( ) " < > \ { }"#
+        );
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "viet sang".to_string();
@@ -1,4 +1,5 @@
 use crate::domain::SubscriberEmail;
+use crate::metrics::observe_email_provider_request;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 use serde::Serialize;
@@ -38,6 +39,7 @@ impl EmailClient {
             auth_token,
         }
     }
+    #[tracing::instrument(name = "Send an email", skip_all)]
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
@@ -54,13 +56,17 @@ impl EmailClient {
             text_body: text_content,
         };
 
-        self.http_client
+        let started_at = std::time::Instant::now();
+        let outcome = self
+            .http_client
             .post(&url)
             .header("X-Postmark-Server-Token", self.auth_token.expose_secret())
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await
+            .and_then(|response| response.error_for_status());
+        observe_email_provider_request(started_at.elapsed(), outcome.is_ok());
+        outcome?;
         Ok(())
     }
 }
@@ -0,0 +1,74 @@
+use crate::configuration::{IdempotencySettings, Settings};
+use crate::startup::get_connection_pool;
+use chrono::Utc;
+use sqlx::PgPool;
+
+//----------------------------------------------------------------
+pub async fn run_idempotency_cleanup_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database)
+        .await
+        .expect("Failed to connect to Postgres.");
+    cleanup_loop(connection_pool, configuration.idempotency).await
+}
+
+async fn cleanup_loop(
+    db_pool: PgPool,
+    settings: IdempotencySettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        // Go through the whole backlog before waiting for the next round
+        loop {
+            match delete_expired_idempotency_keys(
+                &db_pool,
+                settings.retention(),
+                settings.cleanup_batch_size,
+            )
+            .await
+            {
+                Ok(n_deleted) if n_deleted as i64 >= settings.cleanup_batch_size => {}
+                Ok(_) => break,
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to delete expired idempotency keys",
+                    );
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(settings.cleanup_interval()).await;
+    }
+}
+
+/// Delete at most `batch_size` keys older than `retention`, saved responses
+/// included. Returns how many were deleted.
+#[tracing::instrument(name = "Delete expired idempotency keys", skip(db_pool))]
+pub async fn delete_expired_idempotency_keys(
+    db_pool: &PgPool,
+    retention: std::time::Duration,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let expired_before = Utc::now() - chrono::Duration::from_std(retention)?;
+    // Keys of requests still being processed are locked, leave them be
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE (user_id, idempotency_key) IN (
+            SELECT user_id, idempotency_key
+            FROM idempotency
+            WHERE created_at < $1
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        "#,
+        expired_before,
+        batch_size,
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows)
+}
@@ -0,0 +1,143 @@
+use super::IDEMPOTENCY_KEY_FIELD;
+use crate::authentication::CSRF_FIELD_NAME;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// The CSRF token changes with the session and the key is what retries share,
+// neither says anything about what the request asks for.
+const IGNORED_FIELDS: [&str; 2] = [CSRF_FIELD_NAME, IDEMPOTENCY_KEY_FIELD];
+
+/// A hash of what a request asks for, to tell a retry apart from a different
+/// request that reuses the same idempotency key.
+///
+/// Url-encoded and JSON bodies are hashed in a canonical form, so a retry
+/// that sends the same fields in another order shares the fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    pub fn from_request(method: &str, path: &str, content_type: &str, body: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{} {}\n{}\n", method, path, content_type));
+        hasher.update(
+            canonical_body(content_type, body)
+                .as_deref()
+                .unwrap_or(body),
+        );
+        Self(hex::encode(hasher.finalize()))
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// `None` for the bodies we do not know how to normalize, they are hashed
+/// as they are.
+fn canonical_body(content_type: &str, body: &[u8]) -> Option<Vec<u8>> {
+    match content_type {
+        "application/x-www-form-urlencoded" => {
+            let mut pairs: Vec<(String, String)> = serde_urlencoded::from_bytes(body).ok()?;
+            pairs.retain(|(k, _)| !IGNORED_FIELDS.contains(&k.as_str()));
+            pairs.sort();
+            serde_urlencoded::to_string(pairs)
+                .ok()
+                .map(String::into_bytes)
+        }
+        "application/json" => {
+            let mut value: Value = serde_json::from_slice(body).ok()?;
+            if let Value::Object(fields) = &mut value {
+                fields.retain(|k, _| !IGNORED_FIELDS.contains(&k.as_str()));
+            }
+            serde_json::to_vec(&sort_keys(value)).ok()
+        }
+        _ => None,
+    }
+}
+
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.into_iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(fields.into_iter().map(|(k, v)| (k, sort_keys(v))).collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestFingerprint;
+
+    const FORM: &str = "application/x-www-form-urlencoded";
+    const JSON: &str = "application/json";
+
+    #[test]
+    fn only_identical_requests_share_a_fingerprint() {
+        let fingerprint = RequestFingerprint::from_request("POST", "/issues", FORM, b"title=a");
+
+        assert_eq!(
+            fingerprint,
+            RequestFingerprint::from_request("POST", "/issues", FORM, b"title=a")
+        );
+        assert_ne!(
+            fingerprint,
+            RequestFingerprint::from_request("POST", "/issues", FORM, b"title=b")
+        );
+        assert_ne!(
+            fingerprint,
+            RequestFingerprint::from_request("POST", "/subscribers", FORM, b"title=a")
+        );
+        assert_ne!(
+            fingerprint,
+            RequestFingerprint::from_request("POST", "/issues", "text/plain", b"title=a")
+        );
+    }
+
+    #[test]
+    fn the_order_of_form_fields_does_not_matter() {
+        assert_eq!(
+            RequestFingerprint::from_request("POST", "/issues", FORM, b"title=a&text_content=b"),
+            RequestFingerprint::from_request("POST", "/issues", FORM, b"text_content=b&title=a")
+        );
+    }
+
+    #[test]
+    fn the_csrf_token_and_the_key_are_not_part_of_the_fingerprint() {
+        assert_eq!(
+            RequestFingerprint::from_request(
+                "POST",
+                "/issues",
+                FORM,
+                b"title=a&csrf_token=first&idempotency_key=k"
+            ),
+            RequestFingerprint::from_request("POST", "/issues", FORM, b"csrf_token=second&title=a")
+        );
+    }
+
+    #[test]
+    fn json_bodies_are_compared_by_value() {
+        assert_eq!(
+            RequestFingerprint::from_request(
+                "POST",
+                "/issues",
+                JSON,
+                br#"{"title": "a", "content": {"text": "b", "html": "c"}}"#
+            ),
+            RequestFingerprint::from_request(
+                "POST",
+                "/issues",
+                JSON,
+                br#"{"content":{"html":"c","text":"b"},"title":"a"}"#
+            )
+        );
+        assert_ne!(
+            RequestFingerprint::from_request("POST", "/issues", JSON, br#"{"title": "a"}"#),
+            RequestFingerprint::from_request("POST", "/issues", JSON, br#"{"title": "b"}"#)
+        );
+    }
+}
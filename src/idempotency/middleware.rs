@@ -0,0 +1,254 @@
+use super::{
+    save_response, try_processing, IdempotencyKey, NextAction, RequestFingerprint,
+    IN_FLIGHT_RETRY_AFTER_SECONDS,
+};
+use crate::authentication::UserId;
+use crate::configuration::IdempotencySettings;
+use crate::utils::{e500, json_error};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpMessage, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+// Forms can carry the key in a hidden field instead of the header.
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+//----------------------------------------------------------------
+/// The transaction holding the idempotency key of the current request.
+/// Writes done in it are committed together with the saved response, or
+/// rolled back if the handler fails.
+#[derive(Clone)]
+pub struct IdempotencyTransaction(Rc<Mutex<Option<PgTransaction>>>);
+
+impl IdempotencyTransaction {
+    pub async fn lock(&self) -> MappedMutexGuard<'_, PgTransaction> {
+        MutexGuard::map(self.0.lock().await, |t| {
+            t.as_mut()
+                .expect("The transaction is only taken back once the handler is done")
+        })
+    }
+
+    async fn take(&self) -> Option<PgTransaction> {
+        self.0.lock().await.take()
+    }
+}
+
+//----------------------------------------------------------------
+/// Save the response of the wrapped handler under the idempotency key of
+/// the request and replay it to retries, see `try_processing`.
+///
+/// The key comes from the `Idempotency-Key` header or, for url-encoded
+/// forms, the `idempotency_key` field. Keys are scoped to the user, whether
+/// they authenticated with a session or an API token, so the middleware
+/// must sit inside `reject_anonymous_users` or `reject_invalid_api_tokens`.
+/// Server errors are not saved: retrying them processes the request again.
+#[derive(Clone, Copy, Default)]
+pub struct Idempotent {
+    json: bool,
+    on_replay: Option<fn()>,
+}
+
+impl Idempotent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer with a JSON error rather than plain text, for the API.
+    pub fn json(self) -> Self {
+        Self { json: true, ..self }
+    }
+
+    /// Run before replaying a saved response, e.g. to send the flash
+    /// message of the original request again.
+    pub fn on_replay(self, on_replay: fn()) -> Self {
+        Self {
+            on_replay: Some(on_replay),
+            ..self
+        }
+    }
+
+    fn reject(&self, status: StatusCode, message: &str) -> HttpResponse {
+        if self.json {
+            json_error(status, message)
+        } else {
+            HttpResponse::build(status).body(message.to_owned())
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotent
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = IdempotentMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotentMiddleware {
+            service: Rc::new(service),
+            options: *self,
+        }))
+    }
+}
+
+pub struct IdempotentMiddleware<S> {
+    service: Rc<S>,
+    options: Idempotent,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotentMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>
+        + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        Box::pin(process(req, Rc::clone(&self.service), self.options))
+    }
+}
+
+async fn process<S, B>(
+    mut req: ServiceRequest,
+    service: Rc<S>,
+    options: Idempotent,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .context("Idempotent requests must be authenticated first.")
+        .map_err(e500)?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is not registered as application data.")
+        .map_err(e500)?
+        .clone();
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .context("The idempotency settings are not registered as application data.")
+        .map_err(e500)?
+        .clone();
+    // Reading the body consumes it: put it back for the handler
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(body.clone().into());
+
+    let idempotency_key = match submitted_key(&req, &body).map(IdempotencyKey::try_from) {
+        Some(Ok(key)) => key,
+        Some(Err(e)) => {
+            let response = options.reject(StatusCode::BAD_REQUEST, &e.to_string());
+            return Ok(req.into_response(response));
+        }
+        None => {
+            let response = options.reject(
+                StatusCode::BAD_REQUEST,
+                "An idempotency key is required, in the Idempotency-Key header \
+                    or the idempotency_key field.",
+            );
+            return Ok(req.into_response(response));
+        }
+    };
+    let fingerprint = RequestFingerprint::from_request(
+        req.method().as_str(),
+        req.path(),
+        req.content_type(),
+        &body,
+    );
+
+    match try_processing(&db_pool, &idempotency_key, *user_id, &fingerprint, &settings)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(transaction) => {
+            let transaction = IdempotencyTransaction(Rc::new(Mutex::new(Some(transaction))));
+            req.extensions_mut().insert(transaction.clone());
+            let response = service.call(req).await?;
+            let transaction = transaction
+                .take()
+                .await
+                .context("The idempotency transaction is gone.")
+                .map_err(e500)?;
+            if response.status().is_server_error() {
+                // Dropping the transaction rolls back the work and frees the key
+                return Ok(response.map_into_boxed_body());
+            }
+            let (request, response) = response.into_parts();
+            let response = save_response(
+                transaction,
+                &idempotency_key,
+                *user_id,
+                response.map_into_boxed_body(),
+            )
+            .await
+            .map_err(e500)?;
+            Ok(ServiceResponse::new(request, response))
+        }
+        NextAction::ReturnSavedResponse(saved_response) => {
+            if let Some(on_replay) = options.on_replay {
+                on_replay();
+            }
+            Ok(req.into_response(saved_response))
+        }
+        NextAction::InFlight => {
+            let mut response = options.reject(
+                StatusCode::CONFLICT,
+                "A request with the same idempotency key is still being processed.",
+            );
+            response.headers_mut().insert(
+                RETRY_AFTER,
+                HeaderValue::from(IN_FLIGHT_RETRY_AFTER_SECONDS),
+            );
+            Ok(req.into_response(response))
+        }
+        NextAction::FingerprintMismatch => {
+            let response = options.reject(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "The idempotency key was already used for a different request.",
+            );
+            Ok(req.into_response(response))
+        }
+    }
+}
+
+/// Look for the key in the header, then in the body of url-encoded forms.
+fn submitted_key(req: &ServiceRequest, body: &[u8]) -> Option<String> {
+    if let Some(key) = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+    {
+        return Some(key.to_owned());
+    }
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return None;
+    }
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find(|(k, _)| k == IDEMPOTENCY_KEY_FIELD)
+        .map(|(_, v)| v)
+}
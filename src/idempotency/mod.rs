@@ -1,4 +1,15 @@
+mod cleanup;
+mod fingerprint;
 mod key;
+mod middleware;
 mod persistence;
+pub use cleanup::{delete_expired_idempotency_keys, run_idempotency_cleanup_until_stopped};
+pub use fingerprint::RequestFingerprint;
 pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
\ No newline at end of file
+pub use middleware::{
+    Idempotent, IdempotentMiddleware, IdempotencyTransaction, IDEMPOTENCY_KEY_FIELD,
+    IDEMPOTENCY_KEY_HEADER,
+};
+pub use persistence::{
+    get_saved_response, save_response, try_processing, NextAction, IN_FLIGHT_RETRY_AFTER_SECONDS,
+};
\ No newline at end of file
@@ -1,7 +1,9 @@
-use super::IdempotencyKey;
+use super::{IdempotencyKey, RequestFingerprint};
+use crate::configuration::IdempotencySettings;
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use chrono::Utc;
 use sqlx::postgres::PgHasArrayType;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -23,42 +25,102 @@ pub enum NextAction {
     // Return transaction for later usage
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
+    // Another request with the same key did not finish in time
+    InFlight,
+    // The key was already used for a request with a different payload
+    FingerprintMismatch,
 }
+
+// Suggested to clients through `Retry-After` when a request is in flight.
+pub const IN_FLIGHT_RETRY_AFTER_SECONDS: u64 = 5;
+// Postgres: "could not obtain lock"
+const LOCK_NOT_AVAILABLE: &str = "55P03";
 //----------------------------------------------------------------
+/// Keys older than `retention` are processed again as if they were new,
+/// whether or not the cleanup task got to them yet.
+///
+/// A request with the key of one still being processed waits on its row
+/// lock for up to `in_flight_wait`, then replays the saved response.
+/// Responses are only replayed to requests with the same fingerprint.
 pub async fn try_processing(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    fingerprint: &RequestFingerprint,
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
+    let expired_before = Utc::now() - chrono::Duration::from_std(settings.retention())?;
     let mut transaction = db_pool.begin().await?;
-    let n_inserted_rows = sqlx::query!(
+    // A zero lock_timeout would mean waiting forever
+    let lock_timeout = format!("{}ms", settings.in_flight_wait().as_millis().max(1));
+    sqlx::query!("SELECT set_config('lock_timeout', $1, true)", lock_timeout)
+        .fetch_one(&mut transaction)
+        .await?;
+    let n_inserted_rows = match sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_fingerprint,
             created_at
         )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
+        VALUES ($1, $2, $4, now())
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET
+            request_fingerprint = EXCLUDED.request_fingerprint,
+            created_at = now(),
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at < $3
         "#,
         user_id,
         idempotency_key.as_ref(),
+        expired_before,
+        fingerprint.as_ref(),
     )
-    .execute(& mut transaction)
-    .await?
-    .rows_affected();
+    .execute(&mut transaction)
+    .await
+    {
+        Ok(outcome) => outcome.rows_affected(),
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            return Ok(NextAction::InFlight);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // The handler queries must not inherit the timeout
+    sqlx::query!("SET LOCAL lock_timeout TO DEFAULT")
+        .execute(&mut transaction)
+        .await?;
 
     if n_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
     } else {
-        let saved_response = get_saved_response(db_pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(
-                || anyhow::anyhow!("We expected a saved response, we didn't find it")
-            )?;
-        Ok(NextAction::ReturnSavedResponse(saved_response))    
+        let saved_fingerprint = sqlx::query!(
+            r#"
+            SELECT request_fingerprint
+            FROM idempotency
+            WHERE user_id = $1 AND idempotency_key = $2
+            "#,
+            user_id,
+            idempotency_key.as_ref(),
+        )
+        .fetch_optional(db_pool)
+        .await?
+        .and_then(|r| r.request_fingerprint);
+        // Keys saved before fingerprints were introduced are not checked
+        if saved_fingerprint.is_some_and(|f| f != fingerprint.as_ref()) {
+            return Ok(NextAction::FingerprintMismatch);
+        }
+        // No saved response means the request holding the key gave up
+        // without releasing it, retrying later is all we can offer.
+        match get_saved_response(db_pool, idempotency_key, user_id).await? {
+            Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+            None => Ok(NextAction::InFlight),
+        }
     }
 }
+
 pub async fn get_saved_response(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
@@ -66,9 +128,9 @@ pub async fn get_saved_response(
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"SELECT 
-        response_status_code as "response_status_code!",
-        response_headers as "response_headers!: Vec<HeaderPairRecord>",
-        response_body as "response_body!"
+        response_status_code,
+        response_headers as "response_headers: Vec<HeaderPairRecord>",
+        response_body
         FROM idempotency
         WHERE
             user_id =$1 AND idempotency_key = $2
@@ -79,16 +141,21 @@ pub async fn get_saved_response(
     .fetch_optional(db_pool)
     .await?;
 
-    if let Some(r) = saved_response {
-        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for HeaderPairRecord { name, value } in r.response_headers {
-            response.append_header((name, value));
-        }
-        Ok(Some(response.body(r.response_body)))
-    } else {
-        Ok(None)
+    // The response columns stay NULL until the first request saves one
+    let Some(r) = saved_response else {
+        return Ok(None);
+    };
+    let (Some(status_code), Some(headers), Some(body)) =
+        (r.response_status_code, r.response_headers, r.response_body)
+    else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in headers {
+        response.append_header((name, value));
     }
+    Ok(Some(response.body(body)))
 }
 
 pub async fn save_response(
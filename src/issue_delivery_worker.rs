@@ -1,8 +1,13 @@
 use crate::{configuration::Settings, startup::get_connection_pool};
 use crate::{domain::SubscriberEmail, email_client::EmailClient};
-use sqlx::{PgPool, Postgres, Transaction};
+use crate::preferences::{get_preferences_token, is_paused};
+use crate::suppression::get_suppression;
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
+use crate::metrics::{record_issue_delivery, DeliveryOutcome};
+use crate::telemetry::set_parent_from_traceparent;
+use sqlx::{Connection, PgPool, Postgres, Transaction};
 use std::time::Duration;
-use tracing::{field::display, Span};
+use tracing::Span;
 use uuid::Uuid;
 
 pub enum ExecutionOutcome {
@@ -18,12 +23,16 @@ pub async fn run_worker_until_stopped(
     let email_client = configuration.email_client.client();
     
     // );
-    worker_loop(connection_pool, email_client).await
+    worker_loop(connection_pool, email_client, configuration.application.base_url).await
 }
 
-async fn worker_loop(db_pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&db_pool, &email_client).await {
+        match try_execute_task(&db_pool, &email_client, &base_url).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -57,48 +66,123 @@ pub async fn get_issue(db_pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIss
     Ok(issue)
 }
 
+pub async fn try_execute_task(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_task(db_pool).await?;
+    if task.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let (transaction, issue_id, email, traceparent) = task.unwrap();
+    execute_task(
+        db_pool,
+        email_client,
+        base_url,
+        transaction,
+        issue_id,
+        email,
+        traceparent,
+    )
+    .await
+}
+
+/// The delivery is traced as part of the request that published the issue.
+/// Its span must be reparented before any child span is created, children
+/// would stay in the trace they started in otherwise.
 #[tracing::instrument(
+    name = "try_execute_task",
     skip_all,
     fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty,
+        newsletter_issue_id=%issue_id,
+        subscriber_email=%email,
     ),
     err
 )]
-pub async fn try_execute_task(
+async fn execute_task(
     db_pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    mut transaction: PgTransaction,
+    issue_id: Uuid,
+    email: String,
+    traceparent: Option<String>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(db_pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
+    if let Some(traceparent) = traceparent {
+        set_parent_from_traceparent(&Span::current(), &traceparent);
     }
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
+    let mut issue_ids = vec![issue_id];
     // Send email
     match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
-            let issue = get_issue(db_pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
+            if let Some(source) = get_suppression(db_pool, email.as_ref()).await? {
+                tracing::info!(
+                    suppression_source = source.as_str(),
+                    "Skipping a suppressed subscriber.",
+                );
+                record_issue_delivery(DeliveryOutcome::Suppressed);
+                delete_tasks(transaction, &[issue_id], email.as_ref()).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+            // The pause may have started after the delivery was queued or
+            // held for the digest, issues published during a pause are not sent.
+            if is_paused(db_pool, email.as_ref()).await? {
+                tracing::info!("Skipping a paused subscriber.");
+                record_issue_delivery(DeliveryOutcome::Paused);
+                delete_tasks(transaction, &[issue_id], email.as_ref()).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+            let preferences_token = match get_preferences_token(db_pool, email.as_ref()).await? {
+                Some(token) => token,
+                None => {
+                    tracing::info!("Skipping a subscriber who no longer exists.");
+                    record_issue_delivery(DeliveryOutcome::Unsubscribed);
+                    delete_tasks(transaction, &[issue_id], email.as_ref()).await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+            issue_ids = lock_digest_deliveries(&mut transaction, issue_id, email.as_ref()).await?;
+            let mut issues = Vec::with_capacity(issue_ids.len());
+            for issue_id in &issue_ids {
+                issues.push(get_issue(db_pool, *issue_id).await?);
+            }
+            let issue = compose_digest(issues);
+            let preferences_link = format!(
+                "{}/subscriptions/preferences?token={}",
+                base_url, preferences_token
+            );
+            let html_content = format!(
+                "{}<p><a href=\"{}\">Manage your subscription preferences</a></p>",
+                issue.html_content, preferences_link
+            );
+            let text_content = format!(
+                "{}\n\nManage your subscription preferences: {}",
+                issue.text_content, preferences_link
+            );
+            match email_client
+                .send_email(&email, &issue.title, &html_content, &text_content)
                 .await
             {
-                tracing::error!(
-                error.cause_chain = ?e,
-                error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
-                );
+                Ok(()) => {
+                    for issue_id in &issue_ids {
+                        record_issue_delivery(DeliveryOutcome::Sent);
+                        record_issue_delivered(&mut transaction, *issue_id, email.as_ref()).await;
+                    }
+                }
+                Err(e) => {
+                    record_issue_delivery(DeliveryOutcome::Failed);
+                    tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. \
+                    Skipping.",
+                    );
+                }
             }
         }
         Err(e) => {
+            record_issue_delivery(DeliveryOutcome::InvalidAddress);
             tracing::error!(
             error.cause_chain = ?e,
             error.message = %e,
@@ -107,22 +191,110 @@ pub async fn try_execute_task(
             );
         }
     }
-    delete_task(transaction, issue_id, &email).await?;
+    delete_tasks(transaction, &issue_ids, &email).await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Weekly digest subscribers get everything that was held for them in a
+/// single email: lock their other due deliveries as well. Returns the issues
+/// to send, `issue_id` included, oldest first.
+/// Deliveries already picked up by another worker are sent on their own.
+#[tracing::instrument(skip_all)]
+async fn lock_digest_deliveries(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE
+            q.subscriber_email = $1
+            AND (
+                q.newsletter_issue_id = $2
+                OR (
+                    q.execute_after <= now()
+                    AND EXISTS (
+                        SELECT 1 FROM subscriptions s
+                        WHERE s.email = q.subscriber_email
+                        AND s.delivery_frequency = 'weekly_digest'
+                    )
+                )
+            )
+        ORDER BY i.published_at
+        FOR UPDATE OF q
+        SKIP LOCKED
+        "#,
+        email,
+        issue_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.newsletter_issue_id).collect())
+}
+
+/// A single issue is sent as it is, several are put one after the other.
+fn compose_digest(mut issues: Vec<NewsletterIssue>) -> NewsletterIssue {
+    if issues.len() == 1 {
+        return issues.remove(0);
+    }
+    NewsletterIssue {
+        title: format!("Your weekly digest: {} issues", issues.len()),
+        text_content: issues
+            .iter()
+            .map(|issue| format!("{}\n\n{}", issue.title, issue.text_content))
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n"),
+        html_content: issues
+            .iter()
+            .map(|issue| format!("<h2>{}</h2>{}", issue.title, issue.html_content))
+            .collect::<Vec<_>>()
+            .join("<hr>"),
+    }
+}
+
+/// The email is gone already: failing to record the event must not roll
+/// back the delivery, the subscriber would get the issue again.
+async fn record_issue_delivered(transaction: &mut PgTransaction, issue_id: Uuid, email: &str) {
+    let outcome = async {
+        // A failed query aborts the whole transaction, unless it ran in a savepoint
+        let mut savepoint = transaction.begin().await?;
+        record_webhook_event(
+            &mut savepoint,
+            WebhookEventType::IssueDelivered,
+            serde_json::json!({
+                "newsletter_issue_id": issue_id,
+                "email": email,
+            }),
+        )
+        .await?;
+        savepoint.commit().await
+    }
+    .await;
+    if let Err(e) = outcome {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record the delivery of an issue for webhooks.",
+        );
+    }
+}
+
 #[tracing::instrument(skip_all)]
 pub async fn dequeue_task(
     db_pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, Uuid, String, Option<String>)>, anyhow::Error> {
     let mut transaction = db_pool.begin().await?;
     let row = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_email, traceparent
         FROM issue_delivery_queue
+        WHERE execute_after <= now()
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -136,6 +308,7 @@ pub async fn dequeue_task(
             transaction,
             r.newsletter_issue_id,
             r.subscriber_email,
+            r.traceparent,
         )))
     } else {
         Ok(None)
@@ -143,18 +316,18 @@ pub async fn dequeue_task(
 }
 
 #[tracing::instrument(skip_all)]
-pub async fn delete_task(
+pub async fn delete_tasks(
     mut transaction: PgTransaction,
-    issue_id: Uuid,
+    issue_ids: &[Uuid],
     email: &str,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
         WHERE
-            newsletter_issue_id = $1 AND subscriber_email = $2
+            newsletter_issue_id = ANY($1) AND subscriber_email = $2
         "#,
-        issue_id,
+        issue_ids,
         email
     )
     .execute(&mut transaction)
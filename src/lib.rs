@@ -8,4 +8,14 @@ pub mod session_state;
 pub mod utils;
 pub mod authentication;
 pub mod idempotency;
-pub mod issue_delivery_worker;
\ No newline at end of file
+pub mod issue_delivery_worker;
+pub mod data_subject;
+pub mod consent;
+pub mod suppression;
+pub mod preferences;
+pub mod users;
+pub mod audit;
+pub mod user_sessions;
+pub mod outbound_webhooks;
+pub mod webhook_delivery_worker;
+pub mod metrics;
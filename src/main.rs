@@ -1,24 +1,37 @@
 use email_newsletter::telemetry::{get_subscriber, init_subscriber};
 use email_newsletter::configuration::get_configuration;
 use email_newsletter::startup::Application;
+use email_newsletter::idempotency::run_idempotency_cleanup_until_stopped;
 use email_newsletter::issue_delivery_worker::run_worker_until_stopped;
+use email_newsletter::webhook_delivery_worker::run_webhook_worker_until_stopped;
 use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
 
-    let subscriber = get_subscriber("email_newsletter".into(), "info".into(), std::io::stdout);
-    init_subscriber(subscriber);
     // Panic if we can't read the configuration file
     let configuration = get_configuration().expect("Failed to read configuration file");
+    let subscriber = get_subscriber(
+        "email_newsletter".into(),
+        "info".into(),
+        std::io::stdout,
+        configuration.otlp.as_ref(),
+    );
+    init_subscriber(subscriber);
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());    
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let webhook_worker_task = tokio::spawn(run_webhook_worker_until_stopped(configuration.clone()));
+    let idempotency_cleanup_task = tokio::spawn(run_idempotency_cleanup_until_stopped(configuration));
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task => report_exit("Background worker", o),
+        o = webhook_worker_task => report_exit("Webhook worker", o),
+        o = idempotency_cleanup_task => report_exit("Idempotency cleanup", o),
     };
+    // Export the spans still waiting in the batch
+    opentelemetry::global::shutdown_tracer_provider();
     Ok(())
 }
 
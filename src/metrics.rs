@@ -0,0 +1,160 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web_lab::middleware::Next;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+//----------------------------------------------------------------
+// Metrics live in the default registry, shared by the API and the
+// background workers of the process.
+static HTTP_REQUESTS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "HTTP requests handled, by route and response status.",
+        &["method", "route", "status"]
+    )
+    .expect("Failed to register http_requests_total")
+});
+
+static HTTP_REQUEST_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time spent handling HTTP requests, by route.",
+        &["method", "route"]
+    )
+    .expect("Failed to register http_request_duration_seconds")
+});
+
+static ISSUE_EMAILS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "issue_emails_total",
+        "Issue deliveries processed by the worker, by outcome.",
+        &["outcome"]
+    )
+    .expect("Failed to register issue_emails_total")
+});
+
+static EMAIL_PROVIDER_REQUEST_DURATION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "email_provider_request_duration_seconds",
+        "Time spent waiting on the email provider, by outcome.",
+        &["outcome"]
+    )
+    .expect("Failed to register email_provider_request_duration_seconds")
+});
+
+static ISSUE_DELIVERY_QUEUE_DEPTH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "issue_delivery_queue_depth",
+        "Deliveries waiting in the issue delivery queue."
+    )
+    .expect("Failed to register issue_delivery_queue_depth")
+});
+
+static SUBSCRIBERS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!("subscribers", "Subscribers, by status.", &["status"])
+        .expect("Failed to register subscribers")
+});
+
+static DB_POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "db_pool_connections",
+        "Connections of the database pool serving the API, by state.",
+        &["state"]
+    )
+    .expect("Failed to register db_pool_connections")
+});
+
+//----------------------------------------------------------------
+/// What happened to an issue delivery taken off the queue.
+#[derive(Debug, Clone, Copy)]
+pub enum DeliveryOutcome {
+    Sent,
+    Failed,
+    Suppressed,
+    Unsubscribed,
+    Paused,
+    InvalidAddress,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Suppressed => "suppressed",
+            DeliveryOutcome::Unsubscribed => "unsubscribed",
+            DeliveryOutcome::Paused => "paused",
+            DeliveryOutcome::InvalidAddress => "invalid_address",
+        }
+    }
+}
+
+pub fn record_issue_delivery(outcome: DeliveryOutcome) {
+    ISSUE_EMAILS_TOTAL.with_label_values(&[outcome.as_str()]).inc();
+}
+
+pub fn observe_email_provider_request(elapsed: Duration, is_success: bool) {
+    let outcome = if is_success { "success" } else { "error" };
+    EMAIL_PROVIDER_REQUEST_DURATION_SECONDS
+        .with_label_values(&[outcome])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Count every request and time it, labelled with the route pattern rather
+/// than the path to keep the number of series bounded.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started_at = Instant::now();
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".into());
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    HTTP_REQUESTS_TOTAL
+        .with_label_values(&[&method, &route, status.as_str()])
+        .inc();
+    HTTP_REQUEST_DURATION_SECONDS
+        .with_label_values(&[&method, &route])
+        .observe(started_at.elapsed().as_secs_f64());
+    response
+}
+
+/// Sample the gauges backed by the database, then render every metric in
+/// the Prometheus text format.
+pub async fn render_metrics(db_pool: &PgPool) -> Result<String, anyhow::Error> {
+    let queue_depth = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(db_pool)
+        .await?
+        .count;
+    ISSUE_DELIVERY_QUEUE_DEPTH.set(queue_depth);
+    let subscribers_by_status = sqlx::query!(
+        r#"SELECT status, COUNT(*) AS "count!" FROM subscriptions GROUP BY status"#
+    )
+    .fetch_all(db_pool)
+    .await?;
+    // Statuses nobody is in anymore must go back to zero
+    SUBSCRIBERS.reset();
+    for r in subscribers_by_status {
+        SUBSCRIBERS.with_label_values(&[&r.status]).set(r.count);
+    }
+    let idle = db_pool.num_idle() as i64;
+    DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(db_pool.size() as i64 - idle);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
@@ -0,0 +1,234 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::Secret;
+use sha2::Sha256;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+// Same convention as the API tokens, easy to spot if it leaks.
+const WEBHOOK_SECRET_PREFIX: &str = "whsec_";
+
+//----------------------------------------------------------------
+/// What we notify webhook endpoints about.
+#[derive(Debug, Clone, Copy)]
+pub enum WebhookEventType {
+    SubscriberSubscribed,
+    SubscriberConfirmed,
+    SubscriberUnsubscribed,
+    SubscriberBounced,
+    SubscriberComplained,
+    IssuePublished,
+    IssueDelivered,
+}
+
+impl WebhookEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::SubscriberSubscribed => "subscriber.subscribed",
+            WebhookEventType::SubscriberConfirmed => "subscriber.confirmed",
+            WebhookEventType::SubscriberUnsubscribed => "subscriber.unsubscribed",
+            WebhookEventType::SubscriberBounced => "subscriber.bounced",
+            WebhookEventType::SubscriberComplained => "subscriber.complained",
+            WebhookEventType::IssuePublished => "issue.published",
+            WebhookEventType::IssueDelivered => "issue.delivered",
+        }
+    }
+}
+
+pub struct WebhookEndpoint {
+    pub webhook_endpoint_id: Uuid,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An entry of the delivery log.
+pub struct WebhookDelivery {
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub url: String,
+    pub status: String,
+    pub n_attempts: i16,
+    pub execute_after: DateTime<Utc>,
+    pub last_response_status: Option<i16>,
+    pub last_error: Option<String>,
+}
+//----------------------------------------------------------------
+/// Queue an event for every active endpoint.
+/// Pass the transaction of the change the event is about: the event is only
+/// sent if the change is committed.
+#[tracing::instrument(
+    name = "Record webhook event",
+    skip(executor, data),
+    fields(event_type = %event_type.as_str())
+)]
+pub async fn record_webhook_event(
+    executor: impl PgExecutor<'_>,
+    event_type: WebhookEventType,
+    data: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    // Without endpoints there is nobody to tell, the event is not stored.
+    sqlx::query!(
+        r#"
+        WITH event AS (
+            INSERT INTO webhook_events (webhook_event_id, event_type, payload, occurred_at)
+            SELECT $1, $2, $3, now()
+            WHERE EXISTS (SELECT 1 FROM webhook_endpoints WHERE removed_at IS NULL)
+            RETURNING webhook_event_id
+        )
+        INSERT INTO webhook_deliveries (
+            webhook_event_id, webhook_endpoint_id, status, execute_after
+        )
+        SELECT event.webhook_event_id, endpoint.webhook_endpoint_id, 'pending', now()
+        FROM event, webhook_endpoints endpoint
+        WHERE endpoint.removed_at IS NULL
+        "#,
+        Uuid::new_v4(),
+        event_type.as_str(),
+        data.to_string(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Register an endpoint and return the secret its deliveries are signed with.
+#[tracing::instrument(name = "Create webhook endpoint", skip(db_pool))]
+pub async fn create_webhook_endpoint(
+    db_pool: &PgPool,
+    url: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let secret = generate_webhook_secret();
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_endpoints (webhook_endpoint_id, url, secret, created_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        Uuid::new_v4(),
+        url,
+        secret,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the webhook endpoint.")?;
+    Ok(Secret::new(secret))
+}
+
+#[tracing::instrument(name = "List webhook endpoints", skip(db_pool))]
+pub async fn list_webhook_endpoints(db_pool: &PgPool) -> Result<Vec<WebhookEndpoint>, anyhow::Error> {
+    sqlx::query_as!(
+        WebhookEndpoint,
+        r#"
+        SELECT webhook_endpoint_id, url, created_at
+        FROM webhook_endpoints
+        WHERE removed_at IS NULL
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve webhook endpoints.")
+}
+
+/// Stop notifying an endpoint, what is still waiting to be sent to it is
+/// cancelled. Returns `false` if there is no such active endpoint.
+#[tracing::instrument(name = "Remove webhook endpoint", skip(db_pool))]
+pub async fn remove_webhook_endpoint(
+    db_pool: &PgPool,
+    webhook_endpoint_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE webhook_endpoints SET removed_at = now()
+        WHERE webhook_endpoint_id = $1 AND removed_at IS NULL
+        "#,
+        webhook_endpoint_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the webhook endpoint.")?
+    .rows_affected();
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries SET status = 'cancelled'
+        WHERE webhook_endpoint_id = $1 AND status = 'pending'
+        "#,
+        webhook_endpoint_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel the pending webhook deliveries.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to remove a webhook endpoint.")?;
+    Ok(n_updated_rows > 0)
+}
+
+#[tracing::instrument(name = "List recent webhook deliveries", skip(db_pool))]
+pub async fn list_recent_webhook_deliveries(
+    db_pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, anyhow::Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT
+            e.event_type, e.occurred_at, w.url, d.status, d.n_attempts,
+            d.execute_after, d.last_response_status, d.last_error
+        FROM webhook_deliveries d
+        JOIN webhook_events e ON e.webhook_event_id = d.webhook_event_id
+        JOIN webhook_endpoints w ON w.webhook_endpoint_id = d.webhook_endpoint_id
+        ORDER BY e.occurred_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve webhook deliveries.")
+}
+
+/// The value of the signature header: `sha256=` followed by the hex encoded
+/// HMAC-SHA256 of `{timestamp}.{body}`, keyed with the endpoint secret.
+/// Receivers recompute it to check that the delivery comes from us, the
+/// timestamp lets them reject replays.
+pub fn sign_webhook_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn generate_webhook_secret() -> String {
+    let mut rng = thread_rng();
+    let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect();
+    format!("{}{}", WEBHOOK_SECRET_PREFIX, random)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign_webhook_payload;
+
+    #[test]
+    fn the_signature_covers_the_timestamp_and_the_body() {
+        let body = r#"{"type":"subscriber.subscribed"}"#;
+        assert_eq!(
+            sign_webhook_payload("whsec_test", 1700000000, body),
+            "sha256=4158f8f5b98cbd048bb8ead6de4f4f22d7538136b8091faf831ff09697459332"
+        );
+        assert_ne!(
+            sign_webhook_payload("whsec_test", 1700000001, body),
+            sign_webhook_payload("whsec_test", 1700000000, body)
+        );
+    }
+}
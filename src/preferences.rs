@@ -0,0 +1,253 @@
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+//----------------------------------------------------------------
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    // Deliveries are held back and released together once a week.
+    WeeklyDigest,
+}
+
+impl DeliveryFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "every_issue",
+            DeliveryFrequency::WeeklyDigest => "weekly_digest",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryFrequency {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "every_issue" => Ok(Self::EveryIssue),
+            "weekly_digest" => Ok(Self::WeeklyDigest),
+            other => Err(format!(
+                "{} is not a supported delivery frequency. \
+                Use either `every_issue` or `weekly_digest`.",
+                other
+            )),
+        }
+    }
+}
+
+/// What saving preferences does to a pause of deliveries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseChange {
+    // Leave an ongoing pause, or the lack of one, as it is.
+    Keep,
+    Resume,
+    PauseForWeeks(u32),
+}
+
+/// What a subscriber can see and change through their preference link.
+pub struct SubscriberPreferences {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub delivery_frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct Topic {
+    pub topic_id: Uuid,
+    pub name: String,
+}
+//----------------------------------------------------------------
+#[tracing::instrument(name = "Get subscriber preferences", skip(db_pool, preferences_token))]
+pub async fn get_preferences(
+    db_pool: &PgPool,
+    preferences_token: &str,
+) -> Result<Option<SubscriberPreferences>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberPreferences,
+        r#"
+        SELECT
+            id AS subscriber_id, email, name, status,
+            delivery_frequency, paused_until
+        FROM subscriptions
+        WHERE preferences_token = $1
+        "#,
+        preferences_token
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Store new preferences for a subscriber.
+#[tracing::instrument(name = "Update subscriber preferences", skip(transaction, name))]
+pub async fn update_preferences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &str,
+    delivery_frequency: DeliveryFrequency,
+    pause: PauseChange,
+) -> Result<(), sqlx::Error> {
+    let (change_pause, paused_until) = match pause {
+        PauseChange::Keep => (false, None),
+        PauseChange::Resume => (true, None),
+        PauseChange::PauseForWeeks(weeks) => (true, Some(Utc::now() + Duration::weeks(weeks.into()))),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            delivery_frequency = $3,
+            paused_until = CASE WHEN $5 THEN $4 ELSE paused_until END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        name,
+        delivery_frequency.as_str(),
+        paused_until,
+        change_pause,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Replace the set of topics a subscriber has opted out of.
+#[tracing::instrument(name = "Store topic opt-outs", skip(transaction))]
+pub async fn set_topic_opt_outs(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    topic_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM topic_opt_outs WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO topic_opt_outs (subscriber_id, topic_id)
+        SELECT $1, topic_id FROM topics WHERE topic_id = ANY($2)
+        "#,
+        subscriber_id,
+        topic_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get topic opt-outs", skip(executor))]
+pub async fn get_topic_opt_outs(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT topic_id FROM topic_opt_outs WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.topic_id).collect())
+}
+
+/// Mark a subscriber as unsubscribed and drop anything still waiting
+/// to be delivered to them.
+#[tracing::instrument(name = "Unsubscribe subscriber", skip(transaction))]
+pub async fn unsubscribe_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_email = (SELECT email FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions s SET status = 'unsubscribed'
+        FROM (SELECT id, status FROM subscriptions WHERE id = $1 FOR UPDATE) previous
+        WHERE s.id = previous.id
+        RETURNING s.email, previous.status AS "previous_status!"
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if let Some(row) = row.filter(|r| r.previous_status != "unsubscribed") {
+        record_webhook_event(
+            &mut *transaction,
+            WebhookEventType::SubscriberUnsubscribed,
+            serde_json::json!({ "subscriber_id": subscriber_id, "email": row.email }),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get preferences token", skip(executor))]
+pub async fn get_preferences_token(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT preferences_token FROM subscriptions WHERE email = $1"#,
+        email
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|r| r.preferences_token))
+}
+/// Whether the subscriber is in the middle of a pause.
+/// Unknown addresses are not paused.
+pub async fn is_paused(executor: impl PgExecutor<'_>, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COALESCE(paused_until > now(), false) AS "paused!"
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.is_some_and(|r| r.paused))
+}
+//----------------------------------------------------------------
+#[tracing::instrument(name = "List topics", skip(db_pool))]
+pub async fn list_topics(db_pool: &PgPool) -> Result<Vec<Topic>, sqlx::Error> {
+    sqlx::query_as!(
+        Topic,
+        r#"SELECT topic_id, name FROM topics ORDER BY name"#
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Returns `false` if a topic with the same name already exists.
+#[tracing::instrument(name = "Create topic", skip(db_pool))]
+pub async fn insert_topic(db_pool: &PgPool, name: &str) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO topics (topic_id, name, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted_rows > 0)
+}
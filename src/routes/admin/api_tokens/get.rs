@@ -0,0 +1,79 @@
+use crate::authentication::{list_user_api_tokens, CsrfToken, UserId};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn api_tokens_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let tokens = list_user_api_tokens(&db_pool, **user_id)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for t in tokens {
+        writeln!(
+            rows_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>
+                <form action="/admin/api_tokens/revoke" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="api_token_id" value="{}">
+                    <button type="submit">Revoke</button>
+                </form>
+            </td></tr>"#,
+            encode_minimal(&t.name),
+            t.created_at.format("%Y-%m-%d %H:%M UTC"),
+            t.last_used_at
+                .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_else(|| "never".into()),
+            t.api_token_id,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API tokens</title>
+</head>
+<body>
+    {msg_html}
+    <p>API tokens let scripts call <code>/api/v1</code> on your behalf,
+    with the permissions of your role.
+    Send them as <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+    <h2>New token</h2>
+    <form action="/admin/api_tokens" method="post">
+        {csrf_field}
+        <label>Name
+            <input
+                type="text"
+                placeholder="What the token is for"
+                name="name"
+            >
+        </label>
+        <button type="submit">Create token</button>
+    </form>
+    <h2>Active tokens</h2>
+    <table>
+        <tr><th>Name</th><th>Created</th><th>Last used</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
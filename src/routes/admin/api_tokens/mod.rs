@@ -0,0 +1,4 @@
+mod get;
+pub use get::api_tokens_form;
+mod post;
+pub use post::{create_api_token, revoke_api_token};
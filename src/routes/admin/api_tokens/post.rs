@@ -0,0 +1,89 @@
+use crate::authentication::{create_user_api_token, revoke_user_api_token, UserId};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Long enough to tell tokens apart.
+const MAX_TOKEN_NAME_LENGTH: usize = 100;
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    api_token_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Create an API token",
+    skip(form, db_pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_api_token(
+    form: web::Form<CreateFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() || name.chars().count() > MAX_TOKEN_NAME_LENGTH {
+        FlashMessage::error(format!(
+            "The token name must be between 1 and {} characters long.",
+            MAX_TOKEN_NAME_LENGTH
+        ))
+        .send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+    let token = create_user_api_token(&db_pool, **user_id, &name)
+        .await
+        .map_err(e500)?;
+    // Rendered right away rather than after a redirect: this is the only
+    // time the token is ever shown, it must not end up in a cookie.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>New API token</title>
+</head>
+<body>
+    <p>The token {} has been created.</p>
+    <p>Copy it now, it will not be shown again:</p>
+    <p><code id="api-token">{}</code></p>
+    <p><a href="/admin/api_tokens">&lt;- Back</a></p>
+</body>
+</html>"#,
+            encode_minimal(&name),
+            token.expose_secret(),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Revoke an API token",
+    skip(form, db_pool, user_id),
+    fields(user_id=%*user_id, api_token_id=%form.api_token_id)
+)]
+pub async fn revoke_api_token(
+    form: web::Form<RevokeFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Scoped to the current user, so nobody can revoke someone else's token
+    if revoke_user_api_token(&db_pool, **user_id, form.api_token_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
@@ -1,15 +1,20 @@
+use crate::authentication::{CsrfToken, Permission, Role};
 use crate::session_state::TypedSession;
 use crate::utils::e500;
 use actix_web::http::header::{ContentType, LOCATION};
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
 pub async fn admin_dashboard(
     session: TypedSession,
     db_pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
         get_username(user_id, &db_pool).await.map_err(e500)?
     } else {
@@ -17,6 +22,27 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
+    // Only link to the pages the current role can use
+    let mut links_html = String::new();
+    let links = [
+        (None, "/admin/password", "Change password"),
+        (None, "/admin/email", "Account email"),
+        (None, "/admin/totp", "Two-factor authentication"),
+        (None, "/admin/sessions", "Active sessions"),
+        (None, "/admin/api_tokens", "API tokens"),
+        (Some(Permission::PublishIssue), "/admin/newsletters", "Publish a newsletter issue"),
+        (None, "/admin/subscribers", "Subscribers"),
+        (Some(Permission::PublishIssue), "/admin/topics", "Topics"),
+        (Some(Permission::ManageSubscribers), "/admin/suppressions", "Suppression list"),
+        (Some(Permission::ManageSubscribers), "/admin/data_requests", "Data subject requests"),
+        (Some(Permission::ManageUsers), "/admin/users", "Users"),
+        (Some(Permission::ManageWebhooks), "/admin/webhooks", "Webhooks"),
+    ];
+    for (permission, href, label) in links {
+        if permission.into_iter().all(|p| role.has_permission(p)) {
+            writeln!(links_html, r#"<li><a href="{href}">{label}</a></li>"#).unwrap();
+        }
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -32,9 +58,10 @@ pub async fn admin_dashboard(
     <p>Welcome {username}!</p>
     <p>Available actions:</p>
     <ol>
-        <li><a href="/admin/password">Change password</a></li>
+        {links_html}
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
+                {csrf_field}
                 <input type="submit" value="Logout">
             </form>
         </li>
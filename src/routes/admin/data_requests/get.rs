@@ -0,0 +1,86 @@
+use crate::authentication::CsrfToken;
+use crate::data_subject::get_dossier;
+use crate::domain::SubscriberEmail;
+use crate::utils::{e400, e500};
+use actix_web::http::header::{ContentDisposition, ContentType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct QueryData {
+    email: String,
+}
+
+pub async fn data_requests_form(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Data subject requests</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Export</h2>
+    <form action="/admin/data_requests/export" method="get">
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter the subscriber email"
+                name="email"
+            >
+        </label>
+        <button type="submit">Download JSON</button>
+    </form>
+    <h2>Erase</h2>
+    <p>Every subscription, token, consent record, queued delivery and webhook event for this address will be permanently deleted.
+    Suppression list entries are kept so that the address is never mailed again.</p>
+    <form action="/admin/data_requests/erase" method="post">
+        {csrf_field}
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter the subscriber email"
+                name="email"
+            >
+        </label>
+        <br>
+        <label>Confirm email
+            <input
+                type="email"
+                placeholder="Type the email again"
+                name="email_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Erase</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(query, db_pool))]
+pub async fn export_subscriber_data(
+    query: web::Query<QueryData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(query.0.email).map_err(e400)?;
+    let dossier = get_dossier(&db_pool, email.as_ref()).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition::attachment("data-request.json"))
+        .json(dossier))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::{data_requests_form, export_subscriber_data};
+mod post;
+pub use post::erase_subscriber_data;
@@ -0,0 +1,48 @@
+use crate::data_subject::erase_data_subject;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    email_check: String,
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, db_pool))]
+pub async fn erase_subscriber_data(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { email, email_check } = form.0;
+    if email.trim().is_empty() || email != email_check {
+        FlashMessage::error(
+            "You entered two different email addresses - the field values must match.",
+        )
+        .send();
+        return Ok(see_other("/admin/data_requests"));
+    }
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let report = erase_data_subject(&mut transaction, &email)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a data subject.")
+        .map_err(e500)?;
+
+    let email = htmlescape::encode_minimal(&email);
+    if report.is_empty() {
+        FlashMessage::info(format!("No data is stored for {}.", email)).send();
+    } else {
+        FlashMessage::info(format!("All data stored for {} has been erased.", email)).send();
+    }
+    Ok(see_other("/admin/data_requests"))
+}
@@ -0,0 +1,62 @@
+use crate::authentication::{CsrfToken, UserId};
+use crate::users::get_user_email;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn account_email_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let email_html = match get_user_email(&db_pool, **user_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(email) => format!(
+            "<p>Password reset links are sent to <b>{}</b>.</p>",
+            encode_minimal(&email)
+        ),
+        None => "<p>Your account has no email address yet: \
+            you cannot reset a forgotten password.</p>"
+            .to_string(),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Account email</title>
+</head>
+<body>
+    {msg_html}
+    {email_html}
+    <p>We will send a link to the new address, it is used once you follow it.</p>
+    <form action="/admin/email" method="post">
+        {csrf_field}
+        <label>New email
+            <input
+                type="email"
+                placeholder="Enter your email"
+                name="email"
+            >
+        </label>
+        <button type="submit">Send verification link</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
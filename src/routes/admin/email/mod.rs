@@ -0,0 +1,4 @@
+mod get;
+pub use get::account_email_form;
+mod post;
+pub use post::{request_email_change, verify_account_email};
@@ -0,0 +1,117 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::users::{
+    create_email_verification_token, user_email_exists, verify_user_email, VerifyEmailError,
+};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Request an account email change",
+    skip(form, db_pool, email_client, base_url, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn request_email_change(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email.trim().to_owned()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e.to_string())).send();
+            return Ok(see_other("/admin/email"));
+        }
+    };
+    if user_email_exists(&db_pool, email.as_ref()).await.map_err(e500)? {
+        FlashMessage::error(format!(
+            "{} already belongs to an account.",
+            encode_minimal(email.as_ref())
+        ))
+        .send();
+        return Ok(see_other("/admin/email"));
+    }
+    let token = create_email_verification_token(&db_pool, **user_id, email.as_ref())
+        .await
+        .map_err(e500)?;
+    send_verification_email(&email_client, &email, &base_url.0, &token)
+        .await
+        .context("Failed to send the email verification email.")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "A verification link has been sent to {}.",
+        encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/email"))
+}
+
+#[tracing::instrument(
+    name = "Send an email verification email",
+    skip(email_client, base_url, token)
+)]
+async fn send_verification_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &Secret<String>,
+) -> Result<(), reqwest::Error> {
+    let verification_link = format!(
+        "{}/admin/email/verify?token={}",
+        base_url,
+        token.expose_secret()
+    );
+    let plain_body = format!(
+        "Visit {} within the next day to use this address for your newsletter account.\n\
+        If it wasn't you, you can ignore this email.",
+        verification_link
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> within the next day \
+        to use this address for your newsletter account.<br />\
+        If it wasn't you, you can ignore this email.",
+        verification_link
+    );
+    email_client
+        .send_email(email, "Verify your email", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Verify an account email", skip_all, fields(user_id=%*user_id))]
+pub async fn verify_account_email(
+    parameters: web::Query<Parameters>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match verify_user_email(&db_pool, **user_id, &parameters.token).await {
+        Ok(email) => {
+            FlashMessage::info(format!(
+                "{} is now the email of your account.",
+                encode_minimal(&email)
+            ))
+            .send();
+        }
+        Err(VerifyEmailError::UnexpectedError(e)) => return Err(e500(e)),
+        Err(e) => FlashMessage::error(encode_minimal(&e.to_string())).send(),
+    }
+    Ok(see_other("/admin/email"))
+}
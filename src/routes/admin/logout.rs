@@ -1,14 +1,25 @@
 use crate::session_state::TypedSession;
+use crate::user_sessions::revoke_user_session;
 use crate::utils::{e500, see_other};
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        return Ok(see_other("/login"));
-    } else {
-        session.log_out();
-        FlashMessage::info("You have successfully logged out.").send();
-        Ok(see_other("/login"))
+pub async fn log_out(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
+    };
+    // Drop it from the list of active sessions too
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_user_session(db_pool.get_ref(), user_id, session_id)
+            .await
+            .map_err(e500)?;
     }
+    session.log_out();
+    FlashMessage::info("You have successfully logged out.").send();
+    Ok(see_other("/login"))
 }
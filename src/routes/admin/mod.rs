@@ -1,8 +1,28 @@
 mod dashboard;
 mod password;
+mod email;
 mod logout;
 mod newsletter;
+mod data_requests;
+mod subscribers;
+mod suppressions;
+mod topics;
+mod users;
+mod totp;
+mod sessions;
+mod api_tokens;
+mod webhooks;
 pub use dashboard::*;
 pub use password::*;
+pub use email::*;
 pub use logout::*;
-pub use newsletter::*;
\ No newline at end of file
+pub use newsletter::*;
+pub use data_requests::*;
+pub use subscribers::*;
+pub use suppressions::*;
+pub use topics::*;
+pub use users::*;
+pub use totp::*;
+pub use sessions::*;
+pub use api_tokens::*;
+pub use webhooks::*;
\ No newline at end of file
@@ -1,15 +1,33 @@
+use crate::authentication::CsrfToken;
+use crate::preferences::list_topics;
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut topics_html = String::new();
+    for t in list_topics(&db_pool).await.map_err(e500)? {
+        writeln!(
+            topics_html,
+            r#"<option value="{}">{}</option>"#,
+            t.topic_id,
+            encode_minimal(&t.name)
+        )
+        .unwrap();
+    }
     let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -23,6 +41,7 @@ pub async fn publish_newsletter_form(
 <body>
     {msg_html}
     <form action="/admin/newsletters" method="post">
+        {csrf_field}
         <label>Title:<br>
             <input
                 type="text"
@@ -49,6 +68,13 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
+        <label>Topic:<br>
+            <select name="topic_id">
+                <option value="">All subscribers</option>
+                {topics_html}
+            </select>
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
         <button type="submit">Publish</button>
     </form>
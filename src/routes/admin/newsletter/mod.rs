@@ -1,4 +1,7 @@
 mod get;
 pub use get::publish_newsletter_form;
 mod post;
-pub use post::publish_newsletter;
\ No newline at end of file
+pub use post::{
+    enqueue_delivery_tasks, insert_newsletter_issue, publish_newsletter,
+    send_newsletter_accepted_message,
+};
\ No newline at end of file
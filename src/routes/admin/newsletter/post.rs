@@ -1,83 +1,83 @@
 use crate::authentication::UserId;
-use crate::idempotency::{
-    save_response, try_processing, IdempotencyKey, NextAction,
-};
+use crate::idempotency::IdempotencyTransaction;
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
+use crate::telemetry::current_traceparent;
 use crate::utils::{e400, e500, see_other};
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
+// The idempotency key is read by the `Idempotent` middleware.
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
+    // Left empty to send the issue to every subscriber.
+    topic_id: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, db_pool, user_id),
+    skip(form, user_id, transaction),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
-    db_pool: web::Data<PgPool>,
+    transaction: web::ReqData<IdempotencyTransaction>,
 ) -> Result<HttpResponse, actix_web::Error> {
     // We must destructure the form to avoid upsetting the borrow-checker
     let FormData {
         title,
         text_content,
         html_content,
-        idempotency_key,
+        topic_id,
     } = form.0;
-    let user_id = user_id.into_inner();
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let topic_id = topic_id
+        .filter(|id| !id.is_empty())
+        .map(|id| Uuid::parse_str(&id))
+        .transpose()
+        .map_err(e400)?;
 
-    let mut transaction = match try_processing(&db_pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
-            return Ok(saved_response);
-        }
-    };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details.")
-        .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let mut transaction = transaction.lock().await;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        topic_id,
+    )
+    .await
+    .context("Failed to store newsletter issue details.")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, topic_id)
         .await
         .context("Failed to enqueue delivery tasks.")
         .map_err(e500)?;
 
-    success_message().send();
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
-
-    Ok(response)
+    send_newsletter_accepted_message();
+    Ok(see_other("/admin/newsletters"))
 }
 
-fn success_message() -> FlashMessage {
+/// Also sent when the `Idempotent` middleware replays a submission.
+pub fn send_newsletter_accepted_message() {
     FlashMessage::info(
         "The newsletter issue has been accepted - \
             emails will go out shortly.",
     )
+    .send();
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
+    topic_id: Option<Uuid>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -87,36 +87,70 @@ async fn insert_newsletter_issue(
         title,
         text_content,
         html_content,
-        published_at
+        published_at,
+        topic_id
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, now(), $5)
         "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        topic_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_webhook_event(
+        &mut *transaction,
+        WebhookEventType::IssuePublished,
+        serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "title": title,
+            "topic_id": topic_id,
+        }),
     )
-    .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    topic_id: Option<Uuid>,
 ) -> Result<(), sqlx::Error> {
+    // Bounced, complained and unsubscribed subscribers are left out by the
+    // status filter. Paused subscribers miss the issues published while
+    // they are away, weekly digest deliveries wait for the next Monday
+    // to go out together.
+    // Deliveries continue the trace of the request publishing the issue.
     sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
         newsletter_issue_id,
-        subscriber_email
+        subscriber_email,
+        execute_after,
+        traceparent
         )
-        SELECT $1, email
-        FROM subscriptions
+        SELECT
+            $1,
+            email,
+            CASE delivery_frequency
+                WHEN 'weekly_digest' THEN date_trunc('week', now()) + interval '1 week'
+                ELSE now()
+            END,
+            $3
+        FROM subscriptions s
         WHERE status = 'confirmed'
+        AND (paused_until IS NULL OR paused_until <= now())
+        AND NOT EXISTS (
+            SELECT 1 FROM topic_opt_outs o
+            WHERE o.subscriber_id = s.id AND o.topic_id = $2
+        )
         "#,
         newsletter_issue_id,
+        topic_id,
+        current_traceparent(),
     )
     .execute(transaction)
     .await?;
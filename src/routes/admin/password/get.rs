@@ -1,5 +1,6 @@
+use crate::authentication::CsrfToken;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use crate::utils::{e500, see_other};
 use crate::session_state::TypedSession;
 use actix_web_flash_messages::IncomingFlashMessages;
@@ -7,7 +8,9 @@ use std::fmt::Write;
 pub async fn change_password_form(
     session: TypedSession,
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     };
@@ -27,6 +30,7 @@ pub async fn change_password_form(
 <body>
 {msg_html}
 <form action="/admin/password" method="post">
+    {csrf_field}
     <label>Current password
         <input
             type="password"
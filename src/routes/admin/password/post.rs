@@ -1,9 +1,14 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials,UserId};
+use crate::authentication::{
+    validate_credentials, validate_new_password, AuthError, Credentials, UserId,
+};
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
 use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::user_sessions::revoke_other_user_sessions;
 use crate::utils::{e500, see_other};
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use sqlx::PgPool;
 
 #[derive(serde::Deserialize)]
@@ -17,22 +22,28 @@ pub async fn change_password(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    // If two new passwords don't match
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
+    let username = get_username(*user_id, &db_pool).await.map_err(e500)?;
+    if let Err(violations) = validate_new_password(
+        &form.new_password,
+        &form.new_password_check,
+        &username,
+        &password_policy,
+    ) {
+        for violation in violations {
+            FlashMessage::error(violation).send();
+        }
         return Ok(see_other("/admin/password"));
     }
-    let username = get_username(*user_id, &db_pool).await.map_err(e500)?;
     let credentials = Credentials {
         username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &db_pool).await {
+    if let Err(e) = validate_credentials(credentials, &db_pool, &hashing_settings).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -41,7 +52,17 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e).into()),
         };
     }
-    crate::authentication::change_password(*user_id, form.0.new_password, &db_pool)
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &db_pool,
+        &hashing_settings,
+    )
+    .await
+    .map_err(e500)?;
+    // Whoever knew the old password should not stay logged in elsewhere
+    let session_id = session.get_session_id().map_err(e500)?;
+    revoke_other_user_sessions(db_pool.get_ref(), *user_id, session_id)
         .await
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
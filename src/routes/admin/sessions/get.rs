@@ -0,0 +1,86 @@
+use crate::authentication::{CsrfToken, UserId};
+use crate::configuration::SessionSettings;
+use crate::session_state::TypedSession;
+use crate::user_sessions::list_user_sessions;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn sessions_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session_settings: web::Data<SessionSettings>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = list_user_sessions(&db_pool, *user_id, &session_settings)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in sessions {
+        let action_html = if Some(s.session_id) == current_session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                       {csrf_field}
+                    <input hidden type="text" name="session_id" value="{}">
+                    <button type="submit">Revoke</button>
+                </form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.created_at.format("%Y-%m-%d %H:%M UTC"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            encode_minimal(s.ip_address.as_deref().unwrap_or("unknown")),
+            encode_minimal(s.user_agent.as_deref().unwrap_or("unknown")),
+            action_html,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Active sessions</h2>
+    <table>
+        <tr>
+            <th>Signed in</th>
+            <th>Last seen</th>
+            <th>IP address</th>
+            <th>Browser</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <form action="/admin/sessions/revoke_others" method="post">
+        {csrf_field}
+        <button type="submit">Log out all other sessions</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
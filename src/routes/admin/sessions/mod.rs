@@ -0,0 +1,4 @@
+mod get;
+pub use get::sessions_form;
+mod post;
+pub use post::{revoke_other_sessions, revoke_session};
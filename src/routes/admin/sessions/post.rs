@@ -0,0 +1,55 @@
+use crate::authentication::UserId;
+use crate::session_state::TypedSession;
+use crate::user_sessions::{revoke_other_user_sessions, revoke_user_session};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Revoke a session",
+    skip(form, db_pool, user_id),
+    fields(user_id=%*user_id, session_id=%form.session_id)
+)]
+pub async fn revoke_session(
+    form: web::Form<RevokeFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    // Scoped to the current user, so nobody can revoke someone else's session
+    if revoke_user_session(db_pool.get_ref(), *user_id, form.session_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session does not exist or has already ended.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(
+    name = "Revoke other sessions",
+    skip(session, db_pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_other_sessions(
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let session_id = session.get_session_id().map_err(e500)?;
+    let n_revoked = revoke_other_user_sessions(db_pool.get_ref(), *user_id, session_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} other session(s) have been logged out.", n_revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
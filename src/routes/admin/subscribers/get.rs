@@ -0,0 +1,146 @@
+use crate::consent::get_consent_events;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    status: String,
+}
+
+pub async fn list_subscribers(
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = get_subscribers(&db_pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            s.id,
+            encode_minimal(&s.email),
+            encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&db_pool, subscriber_id).await.map_err(e500)? {
+        Some(s) => s,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let consent_events = get_consent_events(&db_pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let mut consent_html = String::new();
+    for e in consent_events {
+        writeln!(
+            consent_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            e.event_type,
+            e.occurred_at.to_rfc3339(),
+            encode_minimal(e.ip_address.as_deref().unwrap_or("-")),
+            encode_minimal(e.user_agent.as_deref().unwrap_or("-")),
+            encode_minimal(&e.source),
+            encode_minimal(&e.consent_text_version),
+        )
+        .unwrap();
+    }
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let status = subscriber.status;
+    let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    <p>Email: {email}</p>
+    <p>Name: {name}</p>
+    <p>Status: {status}</p>
+    <p>Subscribed at: {subscribed_at}</p>
+    <h2>Consent</h2>
+    <table>
+        <tr><th>Event</th><th>At</th><th>IP</th><th>User agent</th><th>Source</th><th>Wording version</th></tr>
+        {consent_html}
+    </table>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(db_pool))]
+async fn get_subscribers(db_pool: &PgPool) -> Result<Vec<SubscriberSummary>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, subscribed_at, status
+        FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    Ok(subscribers)
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(db_pool))]
+async fn get_subscriber(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberSummary>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, subscribed_at, status
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve subscriber.")?;
+    Ok(subscriber)
+}
@@ -0,0 +1,2 @@
+mod get;
+pub use get::{list_subscribers, subscriber_details};
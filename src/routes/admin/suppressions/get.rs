@@ -0,0 +1,94 @@
+use crate::authentication::CsrfToken;
+use crate::suppression::list_suppressions;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn suppressions_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let suppressions = list_suppressions(&db_pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in suppressions {
+        let email = encode_minimal(&s.email);
+        writeln!(
+            rows_html,
+            r#"<tr><td>{email}</td><td>{}</td><td>{}</td><td>{}</td><td>
+                <form action="/admin/suppressions/remove" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="email" value="{email}">
+                    <button type="submit">Remove</button>
+                </form>
+            </td></tr>"#,
+            s.source,
+            encode_minimal(s.reason.as_deref().unwrap_or("-")),
+            s.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Suppression list</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Add an address</h2>
+    <form action="/admin/suppressions" method="post">
+        {csrf_field}
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter the email"
+                name="email"
+            >
+        </label>
+        <label>Source
+            <select name="source">
+                <option value="manual">manual</option>
+                <option value="legal">legal</option>
+                <option value="bounce">bounce</option>
+                <option value="complaint">complaint</option>
+            </select>
+        </label>
+        <label>Reason
+            <input
+                type="text"
+                placeholder="Optional"
+                name="reason"
+            >
+        </label>
+        <button type="submit">Suppress</button>
+    </form>
+    <h2>Bulk upload</h2>
+    <p>One address per line as <code>email,source,reason</code>. Source and reason are optional.</p>
+    <form action="/admin/suppressions/upload" method="post" enctype="multipart/form-data">
+        {csrf_field}
+        <input type="file" name="file" accept=".csv,text/csv">
+        <button type="submit">Upload</button>
+    </form>
+    <h2>Suppressed addresses</h2>
+    <table>
+        <tr><th>Email</th><th>Source</th><th>Reason</th><th>Added at</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::suppressions_form;
+mod post;
+pub use post::{add_suppression, remove_suppressed_email, upload_suppressions};
@@ -0,0 +1,133 @@
+use crate::domain::SubscriberEmail;
+use crate::suppression::{remove_suppression, suppress_email, SuppressionSource};
+use crate::utils::{e500, see_other};
+use actix_multipart::form::{bytes::Bytes, MultipartForm};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    source: String,
+    reason: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    email: String,
+}
+
+#[derive(MultipartForm)]
+pub struct UploadForm {
+    #[multipart(limit = "5MB")]
+    file: Bytes,
+}
+
+#[tracing::instrument(name = "Add a suppressed email", skip(form, db_pool))]
+pub async fn add_suppression(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { email, source, reason } = form.0;
+    let (email, source) = match parse_entry(email, source) {
+        Ok(entry) => entry,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let reason = reason.filter(|r| !r.trim().is_empty());
+    suppress_email(db_pool.get_ref(), email.as_ref(), source, reason.as_deref())
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "{} has been added to the suppression list.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(name = "Remove a suppressed email", skip(form, db_pool))]
+pub async fn remove_suppressed_email(
+    form: web::Form<RemoveFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let removed = remove_suppression(db_pool.get_ref(), &form.email)
+        .await
+        .map_err(e500)?;
+    let email = htmlescape::encode_minimal(&form.email);
+    if removed {
+        FlashMessage::info(format!("{} has been removed from the suppression list.", email)).send();
+    } else {
+        FlashMessage::error(format!("{} is not on the suppression list.", email)).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(name = "Upload a suppression list", skip(form, db_pool))]
+pub async fn upload_suppressions(
+    MultipartForm(form): MultipartForm<UploadForm>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let content = match std::str::from_utf8(&form.file.data) {
+        Ok(content) => content,
+        Err(_) => {
+            FlashMessage::error("The uploaded file is not valid UTF-8 text.").send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let mut n_added = 0;
+    let mut rejected_lines = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let mut columns = line.splitn(3, ',').map(str::trim);
+        let email = columns.next().unwrap_or_default();
+        // Skip blank lines and an optional header row
+        if email.is_empty() || (index == 0 && email.eq_ignore_ascii_case("email")) {
+            continue;
+        }
+        let source = columns.next().filter(|s| !s.is_empty()).unwrap_or("manual");
+        let reason = columns.next().filter(|r| !r.is_empty());
+        match parse_entry(email.to_owned(), source.to_owned()) {
+            Ok((email, source)) => {
+                suppress_email(&mut transaction, email.as_ref(), source, reason)
+                    .await
+                    .map_err(e500)?;
+                n_added += 1;
+            }
+            Err(_) => rejected_lines.push((index + 1).to_string()),
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store suppressed emails.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("{} addresses have been added to the suppression list.", n_added))
+        .send();
+    if !rejected_lines.is_empty() {
+        FlashMessage::error(format!(
+            "The following lines were skipped because they are invalid: {}.",
+            rejected_lines.join(", ")
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+fn parse_entry(
+    email: String,
+    source: String,
+) -> Result<(SubscriberEmail, SuppressionSource), String> {
+    let email = SubscriberEmail::parse(email.trim().to_owned()).map_err(|e| e.to_string())?;
+    let source = SuppressionSource::try_from(source)?;
+    Ok((email, source))
+}
@@ -0,0 +1,56 @@
+use crate::authentication::CsrfToken;
+use crate::preferences::list_topics;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn topics_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let topics = list_topics(&db_pool).await.map_err(e500)?;
+    let mut topics_html = String::new();
+    for t in topics {
+        writeln!(topics_html, "<li>{}</li>", encode_minimal(&t.name)).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Topics</title>
+</head>
+<body>
+    {msg_html}
+    <p>Subscribers receive every topic unless they opt out from their preference page.</p>
+    <form action="/admin/topics" method="post">
+        {csrf_field}
+        <label>Name
+            <input
+                type="text"
+                placeholder="Enter the topic name"
+                name="name"
+            >
+        </label>
+        <button type="submit">Add topic</button>
+    </form>
+    <ul>
+        {topics_html}
+    </ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
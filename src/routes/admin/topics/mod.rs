@@ -0,0 +1,4 @@
+mod get;
+pub use get::topics_form;
+mod post;
+pub use post::create_topic;
@@ -0,0 +1,30 @@
+use crate::preferences::insert_topic;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a topic", skip(form, db_pool))]
+pub async fn create_topic(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error("The topic name cannot be empty.").send();
+        return Ok(see_other("/admin/topics"));
+    }
+    let created = insert_topic(&db_pool, &name).await.map_err(e500)?;
+    let name = htmlescape::encode_minimal(&name);
+    if created {
+        FlashMessage::info(format!("The topic {} has been created.", name)).send();
+    } else {
+        FlashMessage::error(format!("The topic {} already exists.", name)).send();
+    }
+    Ok(see_other("/admin/topics"))
+}
@@ -0,0 +1,101 @@
+use crate::authentication::{
+    generate_totp_secret, get_totp_secret, provisioning_qr_code, provisioning_uri, CsrfToken,
+    UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn totp_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let content_html = if get_totp_secret(&db_pool, *user_id)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        format!(
+            r#"<p>Two-factor authentication is enabled.</p>
+    <form action="/admin/totp/disable" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input
+                type="text"
+                placeholder="6-digit code or recovery code"
+                name="code"
+            >
+        </label>
+        <button type="submit">Disable two-factor authentication</button>
+    </form>"#
+        )
+    } else {
+        // Keep the same secret across reloads until enrollment is confirmed
+        let secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(secret) => Secret::new(secret),
+            None => {
+                let secret = generate_totp_secret();
+                session
+                    .insert_pending_totp_secret(secret.expose_secret())
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &db_pool).await.map_err(e500)?;
+        let uri = provisioning_uri(&secret, &username);
+        let qr_code = provisioning_qr_code(&uri).map_err(e500)?;
+        let uri = encode_minimal(&uri);
+        format!(
+            r#"<p>Scan this QR code with your authenticator app,
+    or enter the secret manually.</p>
+    {qr_code}
+    <p><a href="{uri}">{uri}</a></p>
+    <p>Secret: <code>{secret}</code></p>
+    <form action="/admin/totp/enable" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input
+                type="text"
+                inputmode="numeric"
+                placeholder="Enter the 6-digit code"
+                name="code"
+            >
+        </label>
+        <button type="submit">Enable two-factor authentication</button>
+    </form>"#,
+            secret = secret.expose_secret(),
+        )
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {msg_html}
+    {content_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::totp_form;
+mod post;
+pub use post::{disable_two_factor, enable_two_factor};
@@ -0,0 +1,96 @@
+use crate::authentication::{
+    disable_totp, enable_totp, get_totp_secret, is_valid_totp_code, verify_second_factor,
+    UserId,
+};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(form, session, db_pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn enable_two_factor(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let secret = match session.get_pending_totp_secret().map_err(e500)? {
+        Some(secret) => Secret::new(secret),
+        None => return Ok(see_other("/admin/totp")),
+    };
+    if !is_valid_totp_code(&secret, &form.code) {
+        FlashMessage::error("The authentication code is invalid.").send();
+        return Ok(see_other("/admin/totp"));
+    }
+    let recovery_codes = enable_totp(&db_pool, *user_id, &secret)
+        .await
+        .map_err(e500)?;
+    session.remove_pending_totp_secret();
+
+    // Recovery codes are stored hashed: this page is the only time they are shown
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    <p>Two-factor authentication has been enabled.</p>
+    <p>Store these recovery codes somewhere safe.
+    Each of them can be used once if you lose access to your authenticator app.</p>
+    <ul>
+        {codes_html}
+    </ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(form, db_pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn disable_two_factor(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let secret = match get_totp_secret(&db_pool, *user_id).await.map_err(e500)? {
+        Some(secret) => secret,
+        None => return Ok(see_other("/admin/totp")),
+    };
+    if !verify_second_factor(&db_pool, *user_id, &secret, &form.code)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The authentication code is invalid.").send();
+        return Ok(see_other("/admin/totp"));
+    }
+    disable_totp(&db_pool, *user_id).await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/totp"))
+}
@@ -0,0 +1,97 @@
+use crate::authentication::{CsrfToken, UserId};
+use crate::users::list_users;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn list_users_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let users = list_users(&db_pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for u in users {
+        // Owners cannot lock themselves out
+        let actions_html = if u.user_id == **user_id || !u.is_active {
+            String::new()
+        } else {
+            format!(
+                r#"<form action="/admin/users/role" method="post">
+                       {csrf_field}
+                    <input hidden type="text" name="user_id" value="{id}">
+                    <select name="role">
+                        <option value="owner">owner</option>
+                        <option value="editor">editor</option>
+                        <option value="viewer">viewer</option>
+                    </select>
+                    <button type="submit">Change role</button>
+                </form>
+                <form action="/admin/users/deactivate" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="user_id" value="{id}">
+                    <button type="submit">Deactivate</button>
+                </form>"#,
+                id = u.user_id,
+            )
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{actions_html}</td></tr>",
+            encode_minimal(&u.username),
+            encode_minimal(u.email.as_deref().unwrap_or("-")),
+            u.role,
+            if u.is_active { "active" } else { "deactivated" },
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Users</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Invite a collaborator</h2>
+    <form action="/admin/users/invite" method="post">
+        {csrf_field}
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter the email"
+                name="email"
+            >
+        </label>
+        <label>Role
+            <select name="role">
+                <option value="viewer">viewer</option>
+                <option value="editor">editor</option>
+                <option value="owner">owner</option>
+            </select>
+        </label>
+        <button type="submit">Send invitation</button>
+    </form>
+    <h2>Users</h2>
+    <table>
+        <tr><th>Username</th><th>Email</th><th>Role</th><th>Status</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
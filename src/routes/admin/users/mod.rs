@@ -0,0 +1,4 @@
+mod get;
+pub use get::list_users_form;
+mod post;
+pub use post::{change_user_role, deactivate_user_account, invite_user};
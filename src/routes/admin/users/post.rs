@@ -0,0 +1,152 @@
+use crate::authentication::{Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::users::{create_invitation, deactivate_user, set_user_role, user_email_exists};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    email: String,
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    user_id: Uuid,
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeactivateFormData {
+    user_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, db_pool, email_client, base_url, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let InviteFormData { email, role } = form.0;
+    let (email, role) = match SubscriberEmail::parse(email.trim().to_owned())
+        .map_err(|e| e.to_string())
+        .and_then(|email| Ok((email, Role::try_from(role)?)))
+    {
+        Ok(invite) => invite,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if user_email_exists(&db_pool, email.as_ref()).await.map_err(e500)? {
+        FlashMessage::error(format!(
+            "{} already has an account.",
+            encode_minimal(email.as_ref())
+        ))
+        .send();
+        return Ok(see_other("/admin/users"));
+    }
+    let invitation_token = create_invitation(&db_pool, email.as_ref(), role, **user_id)
+        .await
+        .map_err(e500)?;
+    send_invitation_email(&email_client, &email, role, &base_url.0, &invitation_token)
+        .await
+        .context("Failed to send the invitation email.")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "An invitation has been sent to {}.",
+        encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(
+    name = "Send an invitation email",
+    skip(email_client, base_url, invitation_token)
+)]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    role: Role,
+    base_url: &str,
+    invitation_token: &str,
+) -> Result<(), reqwest::Error> {
+    let invitation_link = format!(
+        "{}/invitations/accept?invitation_token={}",
+        base_url, invitation_token
+    );
+    let plain_body = format!(
+        "You have been invited to help manage our newsletter as {}.\n\
+        Visit {} to choose a username and password.",
+        role, invitation_link
+    );
+    let html_body = format!(
+        "You have been invited to help manage our newsletter as {}.<br />\
+        Click <a href=\"{}\">here</a> to choose a username and password.",
+        role, invitation_link
+    );
+    email_client
+        .send_email(email, "You have been invited", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Change the role of a user", skip(form, db_pool, user_id))]
+pub async fn change_user_role(
+    form: web::Form<RoleFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.user_id == **user_id {
+        FlashMessage::error("You cannot change your own role.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let RoleFormData { user_id, role } = form.0;
+    let new_role = match Role::try_from(role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    if set_user_role(&db_pool, user_id, new_role)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The role has been updated.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(name = "Deactivate a user", skip(form, db_pool, user_id))]
+pub async fn deactivate_user_account(
+    form: web::Form<DeactivateFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.user_id == **user_id {
+        FlashMessage::error("You cannot deactivate your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    if deactivate_user(&db_pool, form.user_id).await.map_err(e500)? {
+        FlashMessage::info("The user has been deactivated.").send();
+    } else {
+        FlashMessage::error("The user does not exist.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
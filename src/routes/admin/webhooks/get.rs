@@ -0,0 +1,114 @@
+use crate::authentication::CsrfToken;
+use crate::outbound_webhooks::{list_recent_webhook_deliveries, list_webhook_endpoints};
+use crate::utils::e500;
+use crate::webhook_delivery_worker::WEBHOOK_SIGNATURE_HEADER;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+// Enough to see what happened lately, older entries stay in the database.
+const N_DELIVERIES_SHOWN: i64 = 50;
+
+pub async fn webhooks_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let csrf_field = csrf_token.form_field();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let endpoints = list_webhook_endpoints(&db_pool).await.map_err(e500)?;
+    let mut endpoints_html = String::new();
+    for e in endpoints {
+        writeln!(
+            endpoints_html,
+            r#"<tr><td>{}</td><td>{}</td><td>
+                <form action="/admin/webhooks/remove" method="post">
+                    {csrf_field}
+                    <input hidden type="text" name="webhook_endpoint_id" value="{}">
+                    <button type="submit">Remove</button>
+                </form>
+            </td></tr>"#,
+            encode_minimal(&e.url),
+            e.created_at.format("%Y-%m-%d %H:%M UTC"),
+            e.webhook_endpoint_id,
+        )
+        .unwrap();
+    }
+    let deliveries = list_recent_webhook_deliveries(&db_pool, N_DELIVERIES_SHOWN)
+        .await
+        .map_err(e500)?;
+    let mut deliveries_html = String::new();
+    for d in deliveries {
+        let next_attempt = if d.status == "pending" {
+            d.execute_after.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+        } else {
+            String::new()
+        };
+        let last_outcome = match (d.last_response_status, &d.last_error) {
+            (_, Some(error)) => encode_minimal(error),
+            (Some(status), None) => status.to_string(),
+            (None, None) => String::new(),
+        };
+        writeln!(
+            deliveries_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            d.occurred_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            encode_minimal(&d.event_type),
+            encode_minimal(&d.url),
+            d.status,
+            d.n_attempts,
+            last_outcome,
+            next_attempt,
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Webhooks</title>
+</head>
+<body>
+    {msg_html}
+    <p>Webhook endpoints are told about subscriptions, confirmations,
+    unsubscriptions, bounces, published issues and delivered issues.
+    Every delivery is signed, see the <code>{WEBHOOK_SIGNATURE_HEADER}</code> header.</p>
+    <h2>New endpoint</h2>
+    <form action="/admin/webhooks" method="post">
+        {csrf_field}
+        <label>URL
+            <input
+                type="text"
+                placeholder="https://crm.example.com/hooks/newsletter"
+                name="url"
+            >
+        </label>
+        <button type="submit">Add endpoint</button>
+    </form>
+    <h2>Endpoints</h2>
+    <table>
+        <tr><th>URL</th><th>Added</th><th></th></tr>
+        {endpoints_html}
+    </table>
+    <h2>Recent deliveries</h2>
+    <table>
+        <tr>
+            <th>Occurred</th><th>Event</th><th>Endpoint</th><th>Status</th>
+            <th>Attempts</th><th>Last outcome</th><th>Next attempt</th>
+        </tr>
+        {deliveries_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
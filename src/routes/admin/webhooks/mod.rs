@@ -0,0 +1,4 @@
+mod get;
+pub use get::webhooks_form;
+mod post;
+pub use post::{add_webhook, remove_webhook};
@@ -0,0 +1,79 @@
+use crate::outbound_webhooks::{create_webhook_endpoint, remove_webhook_endpoint};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    url: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    webhook_endpoint_id: Uuid,
+}
+
+#[tracing::instrument(name = "Add a webhook endpoint", skip(form, db_pool))]
+pub async fn add_webhook(
+    form: web::Form<AddFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let url = form.0.url.trim().to_owned();
+    let is_http_url = reqwest::Url::parse(&url)
+        .map(|u| matches!(u.scheme(), "http" | "https"))
+        .unwrap_or(false);
+    if !is_http_url {
+        FlashMessage::error("The endpoint must be an http or https URL.").send();
+        return Ok(see_other("/admin/webhooks"));
+    }
+    let secret = create_webhook_endpoint(&db_pool, &url)
+        .await
+        .map_err(e500)?;
+    // Rendered right away rather than after a redirect, like API tokens:
+    // the secret must not end up in a cookie.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>New webhook endpoint</title>
+</head>
+<body>
+    <p>The endpoint {} has been added.</p>
+    <p>Configure it with this signing secret, it will not be shown again:</p>
+    <p><code id="webhook-secret">{}</code></p>
+    <p><a href="/admin/webhooks">&lt;- Back</a></p>
+</body>
+</html>"#,
+            encode_minimal(&url),
+            secret.expose_secret(),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Remove a webhook endpoint",
+    skip(form, db_pool),
+    fields(webhook_endpoint_id=%form.webhook_endpoint_id)
+)]
+pub async fn remove_webhook(
+    form: web::Form<RemoveFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if remove_webhook_endpoint(&db_pool, form.webhook_endpoint_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The webhook endpoint has been removed.").send();
+    } else {
+        FlashMessage::error("The webhook endpoint does not exist or has already been removed.")
+            .send();
+    }
+    Ok(see_other("/admin/webhooks"))
+}
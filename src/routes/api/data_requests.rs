@@ -0,0 +1,83 @@
+use crate::data_subject::{erase_data_subject, get_dossier, ErasureReport};
+use crate::domain::SubscriberEmail;
+use crate::routes::api::ApiError;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DataRequestQuery {
+    email: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct ErasureRequest {
+    email: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/data_requests/export",
+    tag = "data requests",
+    params(DataRequestQuery),
+    responses(
+        (status = 200, description = "Everything stored about the email address.", body = Object),
+        (status = 400, description = "The email address is invalid.", body = ErrorBody),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+        (
+            status = 403,
+            description = "The role of the token owner cannot manage subscribers.",
+            body = ErrorBody
+        ),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "Export subscriber data through the API", skip(query, db_pool))]
+pub async fn api_export_subscriber_data(
+    query: web::Query<DataRequestQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let email = SubscriberEmail::parse(query.0.email)
+        .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+    let dossier = get_dossier(&db_pool, email.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(dossier))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/data_requests/erase",
+    tag = "data requests",
+    request_body = ErasureRequest,
+    responses(
+        (status = 200, description = "How many rows were erased, per table.", body = ErasureReport),
+        (status = 400, description = "The request is invalid.", body = ErrorBody),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+        (
+            status = 403,
+            description = "The role of the token owner cannot manage subscribers.",
+            body = ErrorBody
+        ),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "Erase subscriber data through the API", skip(body, db_pool))]
+pub async fn api_erase_subscriber_data(
+    body: web::Json<ErasureRequest>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let email = body.0.email;
+    if email.trim().is_empty() {
+        return Err(ApiError::ValidationError("The email cannot be empty.".into()));
+    }
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let report = erase_data_subject(&mut transaction, &email).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a data subject.")?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,111 @@
+use crate::data_subject::ErasureReport;
+use crate::routes::{
+    __path_api_erase_subscriber_data, __path_api_export_subscriber_data, __path_api_get_subscriber,
+    __path_api_list_issues, __path_api_list_subscribers, __path_api_publish_issue,
+    __path_api_unsubscribe_subscriber, __path_confirm, __path_health_check, __path_metrics,
+    __path_postmark_webhook, __path_subscribe, ErasureRequest, FieldError, FormData, IssueSummary,
+    PostmarkEvent, PublishIssueRequest, PublishedIssue, SubscribeErrorBody, SubscriberRecord,
+    SubscriptionReceived,
+};
+use crate::utils::ErrorBody;
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+//----------------------------------------------------------------
+/// The contract of the endpoints meant for machines rather than browsers.
+/// The admin area and the other HTML pages are left out.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Email newsletter API"),
+    paths(
+        health_check,
+        subscribe,
+        confirm,
+        postmark_webhook,
+        api_publish_issue,
+        api_list_issues,
+        api_list_subscribers,
+        api_get_subscriber,
+        api_unsubscribe_subscriber,
+        api_export_subscriber_data,
+        api_erase_subscriber_data,
+        metrics,
+    ),
+    components(schemas(
+        ErasureReport,
+        ErasureRequest,
+        ErrorBody,
+        FieldError,
+        FormData,
+        IssueSummary,
+        PostmarkEvent,
+        PublishIssueRequest,
+        PublishedIssue,
+        SubscribeErrorBody,
+        SubscriberRecord,
+        SubscriptionReceived,
+    )),
+    modifiers(&SecuritySchemes)
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("A personal API token, created in the admin area."))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "postmark_webhook",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+        components.add_security_scheme(
+            "metrics_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("The token from the metrics settings."))
+                    .build(),
+            ),
+        );
+    }
+}
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Swagger UI, loaded from a CDN, pointed at `openapi_json`.
+pub async fn api_docs() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API documentation</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        window.onload = () => {
+            window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+        };
+    </script>
+</body>
+</html>"##,
+        )
+}
@@ -0,0 +1,48 @@
+use crate::routes::error_chain_fmt;
+use crate::utils::json_error;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+//----------------------------------------------------------------
+/// Errors of the JSON API, rendered as `{"error": "..."}`.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The requested resource does not exist.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // The root cause is logged, not sent to the client
+            ApiError::UnexpectedError(_) => {
+                json_error(self.status_code(), "Something went wrong on our side.")
+            }
+            _ => json_error(self.status_code(), &self.to_string()),
+        }
+    }
+}
+
+/// Turn the rejections of the `Json`, `Path` and `Query` extractors into
+/// JSON errors as well.
+pub fn api_extractor_error<E: std::fmt::Display>(e: E, _: &HttpRequest) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
@@ -0,0 +1,135 @@
+use crate::authentication::UserId;
+use crate::idempotency::IdempotencyTransaction;
+use crate::preferences::list_topics;
+use crate::routes::api::ApiError;
+use crate::routes::{enqueue_delivery_tasks, insert_newsletter_issue};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PublishIssueRequest {
+    title: String,
+    text_content: String,
+    html_content: String,
+    // Left out to send the issue to every subscriber.
+    topic_id: Option<Uuid>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+    topic_id: Option<Uuid>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "issues",
+    request_body = PublishIssueRequest,
+    params(
+        (
+            "Idempotency-Key" = String,
+            Header,
+            description = "Unique per issue, retries of the same request reuse it."
+        ),
+    ),
+    responses(
+        (status = 202, description = "The issue will be delivered shortly.", body = PublishedIssue),
+        (status = 400, description = "The request is invalid.", body = ErrorBody),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+        (status = 403, description = "The role of the token owner cannot publish.", body = ErrorBody),
+        (
+            status = 409,
+            description = "A request with the same key is still being processed, retry after `Retry-After` seconds.",
+            body = ErrorBody
+        ),
+        (
+            status = 422,
+            description = "The key was already used for a request with a different payload.",
+            body = ErrorBody
+        ),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip(body, db_pool, user_id, transaction),
+    fields(user_id=%*user_id)
+)]
+pub async fn api_publish_issue(
+    body: web::Json<PublishIssueRequest>,
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    transaction: web::ReqData<IdempotencyTransaction>,
+) -> Result<HttpResponse, ApiError> {
+    let PublishIssueRequest {
+        title,
+        text_content,
+        html_content,
+        topic_id,
+    } = body.0;
+    if title.trim().is_empty() {
+        return Err(ApiError::ValidationError("The title cannot be empty.".into()));
+    }
+    if let Some(topic_id) = topic_id {
+        let topics = list_topics(&db_pool)
+            .await
+            .context("Failed to retrieve topics.")?;
+        if !topics.iter().any(|t| t.topic_id == topic_id) {
+            return Err(ApiError::ValidationError("The topic does not exist.".into()));
+        }
+    }
+
+    let mut transaction = transaction.lock().await;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        topic_id,
+    )
+    .await
+    .context("Failed to store newsletter issue details.")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, topic_id)
+        .await
+        .context("Failed to enqueue delivery tasks.")?;
+
+    Ok(HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id: issue_id,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues",
+    tag = "issues",
+    responses(
+        (status = 200, description = "Every published issue, latest first.", body = [IssueSummary]),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "List newsletter issues through the API", skip(db_pool))]
+pub async fn api_list_issues(db_pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, published_at, topic_id
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issues.")?;
+    Ok(HttpResponse::Ok().json(issues))
+}
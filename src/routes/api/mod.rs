@@ -0,0 +1,10 @@
+mod data_requests;
+mod docs;
+mod error;
+mod issues;
+mod subscribers;
+pub use data_requests::*;
+pub use docs::*;
+pub use error::*;
+pub use issues::*;
+pub use subscribers::*;
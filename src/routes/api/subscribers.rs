@@ -0,0 +1,139 @@
+use crate::preferences::unsubscribe_subscriber;
+use crate::routes::api::ApiError;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscribersQuery {
+    // e.g. `confirmed`, all subscribers when left out.
+    status: Option<String>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriberRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    params(SubscribersQuery),
+    responses(
+        (status = 200, description = "The subscribers, latest first.", body = [SubscriberRecord]),
+        (status = 400, description = "The query is invalid.", body = ErrorBody),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "List subscribers through the API", skip(query, db_pool))]
+pub async fn api_list_subscribers(
+    query: web::Query<SubscribersQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscribers = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at DESC
+        "#,
+        query.0.status
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    Ok(HttpResponse::Ok().json(subscribers))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "The id of the subscriber.")),
+    responses(
+        (status = 200, description = "The subscriber.", body = SubscriberRecord),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+        (status = 404, description = "There is no such subscriber.", body = ErrorBody),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "Get subscriber through the API", skip(db_pool))]
+pub async fn api_get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = get_subscriber_record(&db_pool, subscriber_id.into_inner())
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Unsubscribe rather than delete, erasure goes through the data subject
+/// requests, see `api_erase_subscriber_data`.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "The id of the subscriber.")),
+    responses(
+        (status = 204, description = "The subscriber is unsubscribed."),
+        (status = 401, description = "The API token is missing or invalid.", body = ErrorBody),
+        (
+            status = 403,
+            description = "The role of the token owner cannot manage subscribers.",
+            body = ErrorBody
+        ),
+        (status = 404, description = "There is no such subscriber.", body = ErrorBody),
+    ),
+    security(("api_token" = []))
+)]
+#[tracing::instrument(name = "Unsubscribe subscriber through the API", skip(db_pool))]
+pub async fn api_unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber_id = subscriber_id.into_inner();
+    if get_subscriber_record(&db_pool, subscriber_id).await?.is_none() {
+        return Err(ApiError::NotFound);
+    }
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    unsubscribe_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn get_subscriber_record(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberRecord>, anyhow::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve subscriber.")
+}
@@ -0,0 +1,78 @@
+use crate::users::get_pending_invitation;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    invitation_token: String,
+}
+
+pub async fn accept_invitation_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation = match get_pending_invitation(&db_pool, &parameters.invitation_token)
+        .await
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Accept invitation</title>
+</head>
+<body>
+    {msg_html}
+    <p>{email} has been invited as {role}. This invitation expires on {expires_at}.</p>
+    <form action="/invitations/accept" method="post">
+        <input hidden type="text" name="invitation_token" value="{token}">
+        <label>Username
+            <input
+                type="text"
+                placeholder="Choose a username"
+                name="username"
+            >
+        </label>
+        <br>
+        <label>Password
+            <input
+                type="password"
+                placeholder="Enter a password"
+                name="password"
+            >
+        </label>
+        <br>
+        <label>Confirm password
+            <input
+                type="password"
+                placeholder="Type the password again"
+                name="password_check"
+            >
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+            email = encode_minimal(&invitation.email),
+            role = invitation.role,
+            expires_at = invitation.expires_at.format("%Y-%m-%d"),
+            token = encode_minimal(&parameters.invitation_token),
+        )))
+}
@@ -0,0 +1,4 @@
+mod get;
+pub use get::accept_invitation_form;
+mod post;
+pub use post::accept_invitation;
@@ -0,0 +1,75 @@
+use crate::authentication::{compute_password_hash, validate_new_password};
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
+use crate::telemetry::spawn_blocking_with_tracing;
+use crate::users::{create_invited_user, AcceptInvitationError};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    invitation_token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip(form, db_pool, password_policy, hashing_settings),
+    fields(username = %form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        invitation_token,
+        username,
+        password,
+        password_check,
+    } = form.0;
+    let form_location = format!(
+        "/invitations/accept?invitation_token={}",
+        urlencoding::encode(&invitation_token)
+    );
+    let username = username.trim().to_owned();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&form_location));
+    }
+    if let Err(violations) =
+        validate_new_password(&password, &password_check, &username, &password_policy)
+    {
+        for violation in violations {
+            FlashMessage::error(violation).send();
+        }
+        return Ok(see_other(&form_location));
+    }
+    let hashing_settings = hashing_settings.get_ref().clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing_settings))
+        .await
+        .map_err(e500)?
+        .context("Failed to hash password")
+        .map_err(e500)?;
+    match create_invited_user(&db_pool, &invitation_token, &username, password_hash).await {
+        Ok(_) => {
+            FlashMessage::info("Your account has been created. You can now log in.").send();
+            Ok(see_other("/login"))
+        }
+        Err(AcceptInvitationError::InvalidInvitation) => Ok(HttpResponse::Unauthorized().finish()),
+        Err(e @ AcceptInvitationError::UsernameTaken(_))
+        | Err(e @ AcceptInvitationError::EmailTaken(_)) => {
+            FlashMessage::error(encode_minimal(&e.to_string())).send();
+            Ok(see_other(&form_location))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
@@ -1,15 +1,31 @@
+use crate::utils::is_admin_location;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    next: Option<String>,
+}
 //----------------------------------------------------------------
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    query: web::Query<QueryParams>,
+) -> HttpResponse {
     let mut error_html = String::new();
     // Display all messages levels, not just errors!
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let next_html = match query.0.next {
+        Some(next) if is_admin_location(&next) => format!(
+            r#"<input hidden type="text" name="next" value="{}">"#,
+            encode_minimal(&next)
+        ),
+        _ => String::new(),
+    };
 
     HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -25,6 +41,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 <body>
     {error_html}
     <form action="/login" method="POST">
+        {next_html}
         <label>Username
             <input 
                 type="text"
@@ -41,6 +58,7 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
         </label>
         <button type="submit">Login</button>
     </form>
+    <p><a href="/password_reset">Forgot your password?</a></p>
 </body>
 </html>"#,
         ))
@@ -1,9 +1,17 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::audit::{record_audit_event, AuditEvent, AuditEventType};
+use crate::authentication::{
+    get_totp_secret, validate_credentials, AuthError, Credentials, LoginThrottle,
+    LoginThrottleDecision,
+};
+use crate::configuration::PasswordHashingSettings;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
+use crate::user_sessions::{create_user_session, SessionMetadata};
+use crate::utils::{client_ip, is_admin_location};
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
@@ -12,10 +20,12 @@ use sqlx::PgPool;
 pub struct FormData {
     username: String,
     password: Secret<String>,
+    // Page to return to, see `reject_anonymous_users`
+    next: Option<String>,
 }
 
 #[tracing::instrument(
-    skip(form, db_pool, session),
+    skip(form, db_pool, session, throttle, hashing_settings, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 // We are now injecting `PgPool` to retrieve stored credentials from the database
@@ -23,38 +33,111 @@ pub async fn login(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let FormData {
+        username,
+        password,
+        next,
+    } = form.0;
+    // Where to go once logged in, and where to retry from otherwise
+    let next = next.filter(|n| is_admin_location(n));
+    let login_location = match &next {
+        Some(next) => format!("/login?next={}", urlencoding::encode(next)),
+        None => "/login".to_string(),
+    };
+    let redirect = |e| login_redirect(e, &login_location);
+    let ip_address = client_ip(&request);
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    match throttle
+        .check(&username, ip_address.as_deref())
+        .await
+        .map_err(|e| redirect(LoginError::UnexpectedError(e)))?
+    {
+        LoginThrottleDecision::LockedOut { retry_after } => {
+            return Err(redirect(LoginError::TooManyAttempts(
+                retry_after.as_secs(),
+            )));
+        }
+        // Slow down guessing before the lockout kicks in
+        LoginThrottleDecision::Allowed { delay } => tokio::time::sleep(delay).await,
+    }
     let credentials = Credentials {
-        username: form.0.username,
-        password: form.0.password,
+        username: username.clone(),
+        password,
     };
-    tracing::Span::current().record("user_id", tracing::field::display(&credentials.username));
-    match validate_credentials(credentials, &db_pool).await {
+    match validate_credentials(credentials, &db_pool, &hashing_settings).await {
         Ok(user_id) => {
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| redirect(LoginError::UnexpectedError(e)))?;
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let totp_secret = get_totp_secret(&db_pool, user_id)
+                .await
+                .map_err(|e| redirect(LoginError::UnexpectedError(e)))?;
             session.renew();
+            // Users enrolled in TOTP are only logged in once their code checks out
+            if totp_secret.is_some() {
+                session
+                    .insert_pending_user_id(user_id)
+                    .map_err(|e| redirect(LoginError::UnexpectedError(e.into())))?;
+                if let Some(next) = &next {
+                    session
+                        .insert_login_redirect(next)
+                        .map_err(|e| redirect(LoginError::UnexpectedError(e.into())))?;
+                }
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two_factor"))
+                    .finish());
+            }
+            let session_id =
+                create_user_session(&db_pool, user_id, &SessionMetadata::from_request(&request))
+                    .await
+                    .map_err(|e| redirect(LoginError::UnexpectedError(e)))?;
             session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                .insert_user_id(user_id, session_id)
+                .map_err(|e| redirect(LoginError::UnexpectedError(e.into())))?;
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, next.as_deref().unwrap_or("/admin/dashboard")))
                 .finish())
         }
-        Err(e) => {
-            let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
-            };
-            Err(login_redirect(e))
+        Err(AuthError::InvalidCredentials(e)) => {
+            let lockouts = throttle
+                .record_failure(&username, ip_address.as_deref())
+                .await
+                .map_err(|e| redirect(LoginError::UnexpectedError(e)))?;
+            if lockouts.is_empty() {
+                return Err(redirect(LoginError::AuthError(e)));
+            }
+            for scope in lockouts {
+                let event = AuditEvent {
+                    event_type: AuditEventType::LoginLockout,
+                    user_id: None,
+                    username: Some(&username),
+                    ip_address: ip_address.as_deref(),
+                    details: Some(format!("Locked out by {}", scope.as_str())),
+                };
+                record_audit_event(db_pool.get_ref(), &event)
+                    .await
+                    .context("Failed to record the login lockout.")
+                    .map_err(|e| redirect(LoginError::UnexpectedError(e)))?;
+            }
+            Err(redirect(LoginError::TooManyAttempts(
+                throttle.lockout_seconds(),
+            )))
         }
+        Err(e) => Err(redirect(LoginError::UnexpectedError(e.into()))),
     }
 }
 
 // Redirect to the login page with an error message.
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+fn login_redirect(e: LoginError, location: &str) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
-        .insert_header((LOCATION, "/login"))
+        .insert_header((LOCATION, location))
         .finish();
     InternalError::from_response(e, response)
 }
@@ -65,6 +148,16 @@ pub enum LoginError {
     AuthError(#[source] anyhow::Error),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Please try again in {} minutes.",
+        minutes_until(*.0)
+    )]
+    TooManyAttempts(u64),
+}
+
+// Round up, so we never tell a user to come back too early.
+fn minutes_until(seconds: u64) -> u64 {
+    seconds.div_ceil(60).max(1)
 }
 impl std::fmt::Debug for LoginError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
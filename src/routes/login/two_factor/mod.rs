@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::two_factor_form;
+pub use post::verify_two_factor;
@@ -0,0 +1,93 @@
+use crate::audit::{record_audit_event, AuditEvent, AuditEventType};
+use crate::authentication::{get_totp_secret, verify_second_factor, LockoutScope, LoginThrottle};
+use crate::routes::login::post::LoginError;
+use crate::session_state::TypedSession;
+use crate::user_sessions::{create_user_session, SessionMetadata};
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify second factor",
+    skip(form, db_pool, session, throttle, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_pending_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    if let Some(retry_after) = throttle.check_second_factor(user_id).await.map_err(e500)? {
+        return Ok(second_factor_lockout(&session, retry_after.as_secs()));
+    }
+    let verified = match get_totp_secret(&db_pool, user_id).await.map_err(e500)? {
+        Some(secret) => verify_second_factor(&db_pool, user_id, &secret, &form.code)
+            .await
+            .map_err(e500)?,
+        // TOTP was disabled in the meantime: start over
+        None => false,
+    };
+    if !verified {
+        if throttle
+            .record_second_factor_failure(user_id)
+            .await
+            .map_err(e500)?
+        {
+            let ip_address = client_ip(&request);
+            let event = AuditEvent {
+                event_type: AuditEventType::LoginLockout,
+                user_id: Some(user_id),
+                username: None,
+                ip_address: ip_address.as_deref(),
+                details: Some(format!(
+                    "Locked out by {}",
+                    LockoutScope::SecondFactor.as_str()
+                )),
+            };
+            record_audit_event(db_pool.get_ref(), &event)
+                .await
+                .context("Failed to record the second factor lockout.")
+                .map_err(e500)?;
+            return Ok(second_factor_lockout(&session, throttle.lockout_seconds()));
+        }
+        FlashMessage::error("The authentication code is invalid.").send();
+        return Ok(see_other("/login/two_factor"));
+    }
+    throttle
+        .record_second_factor_success(user_id)
+        .await
+        .map_err(e500)?;
+    session.remove_pending_user_id();
+    let location = session
+        .take_login_redirect()
+        .map_err(e500)?
+        .unwrap_or_else(|| "/admin/dashboard".to_string());
+    session.renew();
+    let session_id =
+        create_user_session(&db_pool, user_id, &SessionMetadata::from_request(&request))
+            .await
+            .map_err(e500)?;
+    session.insert_user_id(user_id, session_id).map_err(e500)?;
+    Ok(see_other(&location))
+}
+
+// The password has to be entered again once the lockout is over.
+fn second_factor_lockout(session: &TypedSession, retry_after_seconds: u64) -> HttpResponse {
+    session.remove_pending_user_id();
+    FlashMessage::error(LoginError::TooManyAttempts(retry_after_seconds).to_string()).send();
+    see_other("/login")
+}
@@ -0,0 +1,48 @@
+use crate::configuration::MetricsSettings;
+use crate::metrics::render_metrics;
+use crate::utils::{constant_time_eq, e500};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use prometheus::{Encoder, TextEncoder};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+/// Prometheus scrape endpoint, for scrapers configured with the bearer
+/// token from the settings.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (
+            status = 200,
+            description = "Every metric, in the Prometheus text format.",
+            content_type = "text/plain"
+        ),
+        (status = 401, description = "The bearer token is missing or wrong."),
+    ),
+    security(("metrics_token" = []))
+)]
+pub async fn metrics(
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<MetricsSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if !token.is_some_and(|t| constant_time_eq(t, settings.bearer_token.expose_secret())) {
+        let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return Ok(response);
+    }
+    let body = render_metrics(&db_pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, TextEncoder::new().format_type()))
+        .body(body))
+}
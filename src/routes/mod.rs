@@ -1,13 +1,25 @@
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
 mod home;
 mod login;
 mod admin;
+mod webhooks;
+mod invitations;
+mod password_reset;
+mod api;
+mod metrics;
 
 pub use health_check::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
 pub use home::*;
 pub use login::*;
-pub use admin::*;
\ No newline at end of file
+pub use admin::*;
+pub use webhooks::*;
+pub use invitations::*;
+pub use password_reset::*;
+pub use api::*;
+pub use metrics::*;
\ No newline at end of file
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::{password_reset_form, request_password_reset_form};
+pub use post::{request_password_reset, reset_password_with_token};
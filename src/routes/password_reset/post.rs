@@ -0,0 +1,173 @@
+use crate::authentication::{
+    create_reset_token, get_reset_recipient, get_reset_token_username, reset_password,
+    validate_new_password, LoginThrottle,
+};
+use crate::configuration::{PasswordHashingSettings, PasswordPolicySettings};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{client_ip, e500, see_other};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use tracing::Instrument;
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip(form, db_pool, email_client, base_url, throttle, request)
+)]
+pub async fn request_password_reset(
+    form: web::Form<RequestFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.0.email.trim().to_owned();
+    if throttle
+        .record_password_reset_request(&email, client_ip(&request).as_deref())
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        FlashMessage::error("Too many password reset requests. Please try again later.").send();
+        return Ok(see_other("/password_reset"));
+    }
+    // The same answer is given, just as fast, whether or not the email
+    // belongs to a user: the form cannot be used to find out who has an
+    // account. The lookup and the email happen after answering.
+    tokio::spawn(
+        send_reset_link(db_pool, email_client, base_url, email)
+            .instrument(tracing::Span::current()),
+    );
+    FlashMessage::info(
+        "If an account exists for this email, a link to reset its password is on its way.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+async fn send_reset_link(
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email: String,
+) {
+    let outcome = async {
+        let Some(recipient) = get_reset_recipient(&db_pool, &email).await? else {
+            return Ok(());
+        };
+        match SubscriberEmail::parse(recipient.email) {
+            Ok(email) => {
+                let token = create_reset_token(&db_pool, recipient.user_id).await?;
+                send_password_reset_email(&email_client, &email, &base_url.0, &token)
+                    .await
+                    .context("Failed to send the password reset email.")?;
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.message = %e,
+                    "Skipping a password reset. The stored email of the user is invalid",
+                );
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+    if let Err(e) = outcome {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a password reset link.",
+        );
+    }
+}
+
+#[tracing::instrument(
+    name = "Send a password reset email",
+    skip(email_client, base_url, token)
+)]
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &Secret<String>,
+) -> Result<(), reqwest::Error> {
+    let reset_link = format!(
+        "{}/password_reset/confirm?token={}",
+        base_url,
+        token.expose_secret()
+    );
+    let plain_body = format!(
+        "Someone asked to reset the password of your account.\n\
+        Visit {} within the next hour to choose a new one.\n\
+        If it wasn't you, you can ignore this email.",
+        reset_link
+    );
+    let html_body = format!(
+        "Someone asked to reset the password of your account.<br />\
+        Click <a href=\"{}\">here</a> within the next hour to choose a new one.<br />\
+        If it wasn't you, you can ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(email, "Reset your password", &html_body, &plain_body)
+        .await
+}
+
+#[tracing::instrument(name = "Reset a password", skip_all)]
+pub async fn reset_password_with_token(
+    form: web::Form<ResetFormData>,
+    db_pool: web::Data<PgPool>,
+    password_policy: web::Data<PasswordPolicySettings>,
+    hashing_settings: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ResetFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form.0;
+    let username = match get_reset_token_username(&db_pool, &token)
+        .await
+        .map_err(e500)?
+    {
+        Some(username) => username,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    if let Err(violations) =
+        validate_new_password(&new_password, &new_password_check, &username, &password_policy)
+    {
+        for violation in violations {
+            FlashMessage::error(violation).send();
+        }
+        return Ok(see_other(&format!(
+            "/password_reset/confirm?token={}",
+            urlencoding::encode(&token)
+        )));
+    }
+    match reset_password(&db_pool, &token, new_password, &hashing_settings)
+        .await
+        .map_err(e500)?
+    {
+        Some(_) => {
+            FlashMessage::info("Your password has been reset. You can now log in.").send();
+            Ok(see_other("/login"))
+        }
+        None => Ok(HttpResponse::Unauthorized().finish()),
+    }
+}
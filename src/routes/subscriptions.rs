@@ -1,8 +1,14 @@
+use crate::consent::{record_consent_event, ConsentContext, ConsentEventType, ConsentTextVersion};
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
 use crate::email_client::EmailClient;
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
 use crate::startup::ApplicationBaseUrl;
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use crate::suppression::{get_suppression, SuppressionSource};
+use actix_web::dev::Payload;
+use actix_web::{http::StatusCode, web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use chrono::Utc;
+use std::future::Future;
+use std::pin::Pin;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::Deserialize;
@@ -10,24 +16,128 @@ use sqlx::{PgPool, Postgres, Transaction, Executor};
 use uuid::Uuid;
 use anyhow::Context;
 //----------------------------------------------------------------
-#[derive(Deserialize)]
+#[derive(Deserialize, utoipa::ToSchema)]
 pub struct FormData {
     pub name: String,
     pub email: String,
+    // The form or list the subscriber signed up from.
+    pub source: Option<String>,
+}
+
+/// The body of a subscription request: JSON when sent as `application/json`,
+/// urlencoded form data otherwise.
+/// Errors are reported in the same format as the request.
+pub struct SubscribeRequest {
+    data: FormData,
+    is_json: bool,
+}
+
+impl FromRequest for SubscribeRequest {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let content_type = req.content_type();
+        if content_type == "application/json" || content_type.ends_with("+json") {
+            let json = web::Json::<FormData>::from_request(req, payload);
+            Box::pin(async move {
+                match json.await {
+                    Ok(json) => Ok(Self { data: json.0, is_json: true }),
+                    Err(e) => Err(JsonSubscribeError(SubscribeError::InvalidBody(e.to_string())).into()),
+                }
+            })
+        } else {
+            let form = web::Form::<FormData>::from_request(req, payload);
+            Box::pin(async move {
+                match form.await {
+                    Ok(form) => Ok(Self { data: form.0, is_json: false }),
+                    Err(e) => Err(SubscribeError::InvalidBody(e.to_string()).into()),
+                }
+            })
+        }
+    }
+}
+
+/// A rejected part of a subscription request.
+/// `field` is left out for errors that are not about a single field.
+#[derive(Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    field: Option<&'static str>,
+    code: &'static str,
+    message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, code: &'static str, message: impl std::fmt::Display) -> Self {
+        Self {
+            field: Some(field),
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscribeErrorBody {
+    errors: Vec<FieldError>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriptionReceived {
+    // Always `pending_confirmation`, the subscriber still has to click the
+    // link of the confirmation email.
+    status: &'static str,
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
+    #[error("{}", join_messages(.0))]
+    ValidationError(Vec<FieldError>),
     #[error("{0}")]
-    ValidationError(String),
+    InvalidBody(String),
+    #[error("This email address cannot be subscribed.")]
+    SuppressedEmail,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+fn join_messages(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl SubscribeError {
+    fn field_errors(&self) -> Vec<FieldError> {
+        match self {
+            SubscribeError::ValidationError(errors) => errors.clone(),
+            SubscribeError::InvalidBody(message) => vec![FieldError {
+                field: None,
+                code: "invalid_body",
+                message: message.clone(),
+            }],
+            SubscribeError::SuppressedEmail => {
+                vec![FieldError::new("email", "suppressed", self)]
+            }
+            // The root cause is logged, not sent to the client
+            SubscribeError::UnexpectedError(_) => vec![FieldError {
+                field: None,
+                code: "unexpected",
+                message: "Something went wrong on our side.".into(),
+            }],
+        }
+    }
+}
+
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) | SubscribeError::InvalidBody(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SubscribeError::SuppressedEmail => StatusCode::FORBIDDEN,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -38,24 +148,121 @@ impl std::fmt::Debug for SubscribeError {
     }
 }
 
+/// A `SubscribeError` rendered as `{"errors": [...]}` for JSON requests.
+pub struct JsonSubscribeError(SubscribeError);
+
+impl ResponseError for JsonSubscribeError {
+    fn status_code(&self) -> StatusCode {
+        self.0.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(SubscribeErrorBody {
+            errors: self.0.field_errors(),
+        })
+    }
+}
+impl std::fmt::Debug for JsonSubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+impl std::fmt::Display for JsonSubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(
+        content = FormData,
+        content_type = "application/json",
+        description = "Also accepted as `application/x-www-form-urlencoded`."
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The subscriber was saved and a confirmation email sent. \
+                Form requests get an empty body.",
+            body = SubscriptionReceived,
+            content_type = "application/json"
+        ),
+        (
+            status = 400,
+            description = "The body is malformed, or the name or the email is invalid.",
+            content(
+                ("application/json" = SubscribeErrorBody),
+                ("text/plain" = String)
+            )
+        ),
+        (
+            status = 403,
+            description = "The email cannot be subscribed.",
+            content(
+                ("application/json" = SubscribeErrorBody),
+                ("text/plain" = String)
+            )
+        ),
+        (status = 500, description = "Something went wrong on our side."),
+    )
+)]
 #[allow(clippy::async_yields_async)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, email_client, base_url),
+    skip(body, db_pool, email_client, base_url, request, consent_text_version),
     fields(
-        subscriber_name = %form.name,
-        subscriber_email = %form.email
+        subscriber_name = %body.data.name,
+        subscriber_email = %body.data.email
     )
 )]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    body: SubscribeRequest,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    // `web::Form` is a wrapper around `FormData`
-    // `form.0` gives us access to the underlying `FormData`
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    consent_text_version: web::Data<ConsentTextVersion>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let SubscribeRequest { data, is_json } = body;
+    let result = add_subscriber(
+        data,
+        &db_pool,
+        &email_client,
+        &base_url.0,
+        &consent_text_version,
+        &request,
+    )
+    .await;
+    match result {
+        Ok(()) if is_json => Ok(HttpResponse::Ok().json(SubscriptionReceived {
+            status: "pending_confirmation",
+        })),
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if is_json => Err(JsonSubscribeError(e).into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn add_subscriber(
+    data: FormData,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    consent_text_version: &ConsentTextVersion,
+    request: &HttpRequest,
+) -> Result<(), SubscribeError> {
+    let source = data.source.clone().unwrap_or_else(|| "website".into());
+    let consent = ConsentContext::from_request(request, source, &consent_text_version.0);
+    let new_subscriber: NewSubscriber = data.try_into().map_err(SubscribeError::ValidationError)?;
+    let suppression = get_suppression(db_pool, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?;
+    if suppression == Some(SuppressionSource::Legal) {
+        return Err(SubscribeError::SuppressedEmail);
+    }
     let mut transaction = db_pool
         .begin()
         .await
@@ -63,6 +270,26 @@ pub async fn subscribe(
     let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventType::Subscribed,
+        &consent,
+    )
+    .await
+    .context("Failed to record the consent of a new subscriber.")?;
+    record_webhook_event(
+        &mut transaction,
+        WebhookEventType::SubscriberSubscribed,
+        serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "email": new_subscriber.email.as_ref(),
+            "name": new_subscriber.name.as_ref(),
+            "source": consent.source,
+        }),
+    )
+    .await
+    .context("Failed to record the webhook event of a new subscriber.")?;
 
     let subscription_token = generate_subscription_token();
 
@@ -74,15 +301,15 @@ pub async fn subscribe(
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
     send_confirmation_email(
-        &email_client,
+        email_client,
         new_subscriber,
-        &base_url.0,
+        base_url,
         &subscription_token,
     )
     .await
     .context("Failed to send confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 //----------------------------------------------------------------
@@ -175,12 +402,18 @@ pub async fn send_confirmation_email(
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = Vec<FieldError>;
 
+    /// Both fields are checked, so that every problem is reported at once.
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        let name = SubscriberName::parse(value.name)
+            .map_err(|e| FieldError::new("name", e.code(), e));
+        let email = SubscriberEmail::parse(value.email)
+            .map_err(|e| FieldError::new("email", e.code(), e));
+        match (name, email) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err(name.err().into_iter().chain(email.err()).collect()),
+        }
     }
 }
 
@@ -195,13 +428,14 @@ pub async fn insert_subscriber(
     let subscriber_id = Uuid::new_v4();
     let query = sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, preferences_token)
+    VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
             "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        generate_subscription_token()
     );
 
     // Execute the query within the transaction
@@ -1,19 +1,36 @@
-use actix_web::{HttpResponse, web};
+use crate::consent::{record_consent_event, ConsentContext, ConsentEventType, ConsentTextVersion};
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
+use actix_web::{HttpRequest, HttpResponse, web};
 use uuid::Uuid;
 use sqlx::PgPool;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
     pub subscription_token: String,
+    pub source: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription is confirmed."),
+        (status = 401, description = "The token is unknown."),
+        (status = 500, description = "Something went wrong on our side."),
+    )
+)]
 #[tracing::instrument(
     name = "Confirm a pending subscriber",
-    skip(parameter, db_pool)
+    skip(parameter, db_pool, consent_text_version, request)
 )]
 pub async fn confirm(
     parameter: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
+    consent_text_version: web::Data<ConsentTextVersion>,
+    request: HttpRequest,
 ) -> HttpResponse {
     let id = match get_subscriber_id_from_token(
         &db_pool, 
@@ -26,7 +43,9 @@ pub async fn confirm(
     match id {
         None => HttpResponse::Unauthorized().finish(),
         Some(subscriber_id) => {
-            if confirm_subscriber(&db_pool, subscriber_id).await.is_err() {
+            let source = parameter.0.source.unwrap_or_else(|| "confirmation_email".into());
+            let consent = ConsentContext::from_request(&request, source, &consent_text_version.0);
+            if confirm_subscriber(&db_pool, subscriber_id, &consent).await.is_err() {
                 return HttpResponse::InternalServerError().finish();
             }
             HttpResponse::Ok().finish()
@@ -36,23 +55,52 @@ pub async fn confirm(
 
 #[tracing::instrument(
     name = "Mark subscriber as confirmed",
-    skip(db_pool, subscriber_id)
+    skip(db_pool, subscriber_id, consent)
 )]
 pub async fn confirm_subscriber(
     db_pool: &PgPool,
     subscriber_id: Uuid,
+    consent: &ConsentContext,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+    let mut transaction = db_pool.begin().await?;
+    // Old links stay valid, they must not bring back a subscriber that
+    // bounced, complained or unsubscribed since. Following the link again
+    // must not notify or record consent a second time either.
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        RETURNING email
+        "#,
         subscriber_id
     )
-    .execute(db_pool)
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute the query: {:?}", e);
+        e
+    })?;
+    let Some(row) = row else {
+        return Ok(());
+    };
+    record_webhook_event(
+        &mut transaction,
+        WebhookEventType::SubscriberConfirmed,
+        serde_json::json!({ "subscriber_id": subscriber_id, "email": row.email }),
+    )
+    .await?;
+    record_consent_event(
+        &mut transaction,
+        subscriber_id,
+        ConsentEventType::Confirmed,
+        consent,
+    )
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute the query: {:?}", e);
         e
     })?;
-    Ok(())
+    transaction.commit().await
 }
 
 #[tracing::instrument(
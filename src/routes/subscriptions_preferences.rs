@@ -0,0 +1,258 @@
+use crate::domain::SubscriberName;
+use crate::preferences::{
+    get_preferences, get_topic_opt_outs, list_topics, set_topic_opt_outs,
+    unsubscribe_subscriber, update_preferences, DeliveryFrequency, PauseChange,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+// Longest pause a subscriber can ask for, in weeks.
+const MAX_PAUSE_WEEKS: u32 = 52;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    token: String,
+}
+
+#[tracing::instrument(name = "Show subscriber preferences", skip_all)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = match get_preferences(&db_pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(p) => p,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let token = encode_minimal(&parameters.token);
+    let content_html = if preferences.status == "unsubscribed" {
+        format!(
+            "<p>{} is unsubscribed and will not receive any further issues.</p>",
+            encode_minimal(&preferences.email)
+        )
+    } else {
+        let topics = list_topics(&db_pool).await.map_err(e500)?;
+        let opt_outs = get_topic_opt_outs(db_pool.get_ref(), preferences.subscriber_id)
+            .await
+            .map_err(e500)?;
+        let mut topics_html = String::new();
+        for t in topics {
+            let checked = if opt_outs.contains(&t.topic_id) { "" } else { " checked" };
+            writeln!(
+                topics_html,
+                r#"<label><input type="checkbox" name="topic" value="{}"{checked}> {}</label><br>"#,
+                t.topic_id,
+                encode_minimal(&t.name),
+            )
+            .unwrap();
+        }
+        let frequency_checked = |value: &str| {
+            if preferences.delivery_frequency == value { " checked" } else { "" }
+        };
+        // Saving other preferences must not end a pause, only picking
+        // "Resume" does
+        let (paused_html, pause_options) = match preferences.paused_until {
+            Some(until) if until > chrono::Utc::now() => {
+                let until = until.format("%Y-%m-%d");
+                (
+                    format!("<p>Deliveries are paused until {}.</p>", until),
+                    format!(
+                        r#"<option value="">Stay paused until {}</option>
+                <option value="0">Resume deliveries now</option>"#,
+                        until
+                    ),
+                )
+            }
+            _ => (
+                String::new(),
+                r#"<option value="">Don't pause</option>"#.to_string(),
+            ),
+        };
+        format!(
+            r#"{paused_html}
+    <form action="/subscriptions/preferences" method="post">
+        <input hidden type="text" name="token" value="{token}">
+        <label>Name
+            <input type="text" name="name" value="{name}">
+        </label>
+        <h3>Topics</h3>
+        {topics_html}
+        <h3>Frequency</h3>
+        <label><input type="radio" name="delivery_frequency" value="every_issue"{every_issue}> Every issue</label><br>
+        <label><input type="radio" name="delivery_frequency" value="weekly_digest"{weekly_digest}> Weekly digest</label><br>
+        <h3>Take a break</h3>
+        <label>Pause deliveries for
+            <select name="pause_weeks">
+                {pause_options}
+                <option value="1">1 week</option>
+                <option value="2">2 weeks</option>
+                <option value="4">4 weeks</option>
+                <option value="8">8 weeks</option>
+            </select>
+        </label>
+        <br>
+        <button type="submit">Save preferences</button>
+    </form>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="token" value="{token}">
+        <button type="submit">Unsubscribe from everything</button>
+    </form>"#,
+            name = encode_minimal(&preferences.name),
+            every_issue = frequency_checked("every_issue"),
+            weekly_digest = frequency_checked("weekly_digest"),
+        )
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscription preferences</title>
+</head>
+<body>
+    <h2>Subscription preferences for {email}</h2>
+    {msg_html}
+    {content_html}
+</body>
+</html>"#,
+            email = encode_minimal(&preferences.email),
+        )))
+}
+
+/// The form is read as a list of pairs because browsers send one
+/// `topic` field per checked box.
+#[tracing::instrument(name = "Update subscriber preferences", skip_all)]
+pub async fn save_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field = |key: &str| {
+        form.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.to_owned())
+            .unwrap_or_default()
+    };
+    let token = field("token");
+    let preferences = match get_preferences(&db_pool, &token).await.map_err(e500)? {
+        Some(p) if p.status != "unsubscribed" => p,
+        Some(_) => return Ok(see_other(&preferences_location(&token))),
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let selected_topics: Vec<Uuid> = form
+        .iter()
+        .filter(|(k, _)| k == "topic")
+        .filter_map(|(_, v)| Uuid::parse_str(v).ok())
+        .collect();
+    let name = match SubscriberName::parse(field("name")) {
+        Ok(name) => name,
+        Err(e) => return Ok(reject(&token, &e.to_string())),
+    };
+    let delivery_frequency = match DeliveryFrequency::try_from(field("delivery_frequency")) {
+        Ok(f) => f,
+        Err(e) => return Ok(reject(&token, &e)),
+    };
+    let pause = match field("pause_weeks").as_str() {
+        "" => PauseChange::Keep,
+        weeks => match weeks.parse::<u32>() {
+            Ok(0) => PauseChange::Resume,
+            Ok(weeks) if weeks <= MAX_PAUSE_WEEKS => PauseChange::PauseForWeeks(weeks),
+            _ => {
+                return Ok(reject(
+                    &token,
+                    &format!("Deliveries can be paused for at most {} weeks.", MAX_PAUSE_WEEKS),
+                ))
+            }
+        },
+    };
+    let topic_opt_outs: Vec<Uuid> = list_topics(&db_pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|t| t.topic_id)
+        .filter(|id| !selected_topics.contains(id))
+        .collect();
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    update_preferences(
+        &mut transaction,
+        preferences.subscriber_id,
+        name.as_ref(),
+        delivery_frequency,
+        pause,
+    )
+    .await
+    .map_err(e500)?;
+    set_topic_opt_outs(&mut transaction, preferences.subscriber_id, &topic_opt_outs)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store subscriber preferences.")
+        .map_err(e500)?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_location(&token)))
+}
+
+#[tracing::instrument(name = "Unsubscribe via preferences link", skip_all)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeFormData>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = match get_preferences(&db_pool, &form.token).await.map_err(e500)? {
+        Some(p) => p,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    unsubscribe_subscriber(&mut transaction, preferences.subscriber_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("You have been unsubscribed.").send();
+    Ok(see_other(&preferences_location(&form.token)))
+}
+
+fn reject(token: &str, message: &str) -> HttpResponse {
+    FlashMessage::error(encode_minimal(message)).send();
+    see_other(&preferences_location(token))
+}
+
+fn preferences_location(token: &str) -> String {
+    format!(
+        "/subscriptions/preferences?token={}",
+        urlencoding::encode(token)
+    )
+}
@@ -0,0 +1,2 @@
+mod postmark;
+pub use postmark::*;
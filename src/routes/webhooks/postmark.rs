@@ -0,0 +1,245 @@
+use crate::configuration::PostmarkWebhookSettings;
+use crate::outbound_webhooks::{record_webhook_event, WebhookEventType};
+use crate::routes::error_chain_fmt;
+use crate::suppression::{suppress_email, SuppressionSource};
+use crate::utils::constant_time_eq;
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::ExposeSecret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+//----------------------------------------------------------------
+/// The subset of Postmark's bounce and spam complaint webhook payloads
+/// we act upon.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    email: String,
+    description: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum PostmarkWebhookError {
+    #[error("Authentication failed")]
+    AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PostmarkWebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PostmarkWebhookError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            PostmarkWebhookError::AuthError(_) => {
+                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
+                let header_value = HeaderValue::from_str(r#"Basic realm="webhooks""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            PostmarkWebhookError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
+            PostmarkWebhookError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/postmark",
+    tag = "webhooks",
+    request_body = PostmarkEvent,
+    responses(
+        (status = 200, description = "The event was processed."),
+        (status = 400, description = "The payload is not a valid Postmark event."),
+        (status = 401, description = "The credentials are missing or wrong."),
+        (status = 500, description = "Something went wrong on our side."),
+    ),
+    security(("postmark_webhook" = []))
+)]
+#[tracing::instrument(
+    name = "Handle a Postmark webhook",
+    skip(body, request, db_pool, settings),
+    fields(record_type=tracing::field::Empty, bounce_type=tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    body: web::Bytes,
+    request: HttpRequest,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<PostmarkWebhookSettings>,
+) -> Result<HttpResponse, PostmarkWebhookError> {
+    authenticate(request.headers(), &settings).map_err(PostmarkWebhookError::AuthError)?;
+    let event: PostmarkEvent = serde_json::from_slice(&body)
+        .map_err(|e| PostmarkWebhookError::ValidationError(e.to_string()))?;
+    tracing::Span::current()
+        .record("record_type", tracing::field::display(&event.record_type))
+        .record("bounce_type", tracing::field::debug(&event.bounce_type));
+    let payload = String::from_utf8_lossy(&body);
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    insert_bounce_event(&mut transaction, &event, &payload)
+        .await
+        .context("Failed to store the bounce event.")?;
+    if let Some((status, source, event_type)) = suppressed_status(&event) {
+        suppress_subscriber(&mut transaction, &event.email, status)
+            .await
+            .context("Failed to suppress the subscriber.")?;
+        suppress_email(
+            &mut transaction,
+            &event.email,
+            source,
+            event.description.as_deref(),
+        )
+        .await
+        .context("Failed to add the address to the suppression list.")?;
+        record_webhook_event(
+            &mut transaction,
+            event_type,
+            serde_json::json!({
+                "email": event.email,
+                "record_type": event.record_type,
+                "bounce_type": event.bounce_type,
+            }),
+        )
+        .await
+        .context("Failed to record the webhook event of a bounce.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a bounce event.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The subscription status an event moves the subscriber to, if any,
+/// together with the matching suppression source and webhook event.
+/// Soft bounces are recorded but do not stop delivery.
+fn suppressed_status(
+    event: &PostmarkEvent,
+) -> Option<(&'static str, SuppressionSource, WebhookEventType)> {
+    match (event.record_type.as_str(), event.bounce_type.as_deref()) {
+        ("SpamComplaint", _) => Some((
+            "complained",
+            SuppressionSource::Complaint,
+            WebhookEventType::SubscriberComplained,
+        )),
+        ("Bounce", Some("HardBounce" | "BadEmailAddress")) => Some((
+            "bounced",
+            SuppressionSource::Bounce,
+            WebhookEventType::SubscriberBounced,
+        )),
+        _ => None,
+    }
+}
+
+fn authenticate(
+    headers: &HeaderMap,
+    settings: &PostmarkWebhookSettings,
+) -> Result<(), anyhow::Error> {
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    let (username, password) = decoded_credentials
+        .split_once(':')
+        .context("A password must be provided in 'Basic' auth.")?;
+    // Both fields are always compared, `&` does not short-circuit
+    let username_matches = constant_time_eq(username, &settings.username);
+    let password_matches = constant_time_eq(password, settings.password.expose_secret());
+    if !(username_matches & password_matches) {
+        anyhow::bail!("Invalid webhook credentials.");
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_bounce_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &PostmarkEvent,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO bounce_events (
+            id,
+            email,
+            record_type,
+            bounce_type,
+            description,
+            payload,
+            received_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        event.email,
+        event.record_type,
+        event.bounce_type,
+        event.description,
+        payload,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Stop mailing the address: its status keeps it out of
+/// `enqueue_delivery_tasks` and pending deliveries are dropped.
+#[tracing::instrument(skip(transaction))]
+async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+        status
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = lower($1)
+        "#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
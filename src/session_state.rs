@@ -1,22 +1,93 @@
+use crate::authentication::generate_csrf_token;
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
 use std::future::{Ready, ready};
+use chrono::Utc;
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const LAST_SEEN_AT_KEY: &'static str = "last_seen_at";
+    // Where to go once logged in, e.g. after the session expired
+    const LOGIN_REDIRECT_KEY: &'static str = "login_redirect";
+    // Synchronizer token checked on state-changing admin requests
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    // Row in `user_sessions` tracking this login
+    const SESSION_ID_KEY: &'static str = "session_id";
+    // Set between a correct password and a correct second factor
+    const PENDING_USER_ID_KEY: &'static str = "pending_second_factor_user_id";
+    // Secret shown during TOTP enrollment, until the user confirms it
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
     pub fn renew(&self) {
         self.0.renew();
     }
-    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
-        self.0.insert(Self::USER_ID_KEY, user_id)
+    pub fn insert_user_id(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)?;
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
+        // Never carry a token over from before the login
+        self.0.insert(Self::CSRF_TOKEN_KEY, generate_csrf_token())?;
+        let now = Utc::now().timestamp_millis();
+        self.0.insert(Self::LAST_SEEN_AT_KEY, now)?;
+        self.0.insert(Self::LOGGED_IN_AT_KEY, now)
+    }
+    /// Record activity, pushing back the idle timeout.
+    pub fn touch(&self) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LAST_SEEN_AT_KEY, Utc::now().timestamp_millis())
+    }
+    /// When the session was last used, as a Unix timestamp in milliseconds.
+    pub fn get_last_seen_at(&self) -> Result<Option<i64>, SessionGetError> {
+        self.0.get(Self::LAST_SEEN_AT_KEY)
+    }
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+    pub fn insert_login_redirect(&self, location: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LOGIN_REDIRECT_KEY, location)
+    }
+    pub fn take_login_redirect(&self) -> Result<Option<String>, SessionGetError> {
+        let location = self.0.get(Self::LOGIN_REDIRECT_KEY)?;
+        self.0.remove(Self::LOGIN_REDIRECT_KEY);
+        Ok(location)
+    }
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+    /// When the user logged in, as a Unix timestamp in milliseconds.
+    pub fn get_logged_in_at(&self) -> Result<Option<i64>, SessionGetError> {
+        self.0.get(Self::LOGGED_IN_AT_KEY)
     }
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
+    pub fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+    }
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
     pub fn log_out(self) {
         self.0.purge()
     } 
@@ -1,14 +1,40 @@
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{
+    reject_anonymous_users, reject_invalid_api_tokens, verify_csrf_token, LoginThrottle,
+    Permission, RequirePermission,
+};
 use crate::configuration::{DatabaseSettings, Settings};
+use crate::consent::ConsentTextVersion;
 use crate::email_client::EmailClient;
+use crate::idempotency::Idempotent;
+use crate::metrics::record_http_metrics;
 use crate::routes::{
     health_check, home, subscribe,
-    confirm, publish_newsletter,publish_newsletter_form,
+    confirm, publish_newsletter,publish_newsletter_form, send_newsletter_accepted_message,
     login, login_form, log_out, admin_dashboard,
     change_password, change_password_form,
+    account_email_form, request_email_change, verify_account_email,
+    data_requests_form, export_subscriber_data, erase_subscriber_data,
+    list_subscribers, subscriber_details, postmark_webhook,
+    suppressions_form, add_suppression, remove_suppressed_email, upload_suppressions,
+    preferences_form, save_preferences, unsubscribe, topics_form, create_topic,
+    list_users_form, invite_user, change_user_role, deactivate_user_account,
+    accept_invitation_form, accept_invitation,
+    totp_form, enable_two_factor, disable_two_factor, two_factor_form, verify_two_factor,
+    sessions_form, revoke_session, revoke_other_sessions,
+    api_tokens_form, create_api_token, revoke_api_token, webhooks_form, add_webhook, remove_webhook,
+    api_docs, openapi_json, api_extractor_error, api_publish_issue, api_list_issues,
+    api_list_subscribers, api_get_subscriber, api_unsubscribe_subscriber,
+    api_export_subscriber_data, api_erase_subscriber_data,
+    request_password_reset_form, request_password_reset, password_reset_form,
+    reset_password_with_token, metrics,
 };
+use crate::utils::TrustedProxies;
+use actix_cors::Cors;
 use actix_web::dev::Server;
-use actix_web::{web, App, HttpServer, cookie::Key};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Condition;
+use actix_web::{web, App, HttpServer, Route, cookie::Key};
+use actix_web::cookie::time::Duration;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
@@ -17,6 +43,7 @@ use secrecy::{Secret, ExposeSecret};
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_session::SessionMiddleware;
+use actix_session::config::BrowserSession;
 use actix_session::storage::RedisSessionStore;
 use actix_web_lab::middleware::from_fn;
 
@@ -44,7 +71,7 @@ impl Application {
         //     configuration.email_client.auth_token,
         //     timeout,
         // );
-        let email_client = configuration.email_client.client();
+        let email_client = configuration.email_client.clone().client();
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -55,9 +82,7 @@ impl Application {
             listener, 
             connection_pool, 
             email_client, 
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
-            configuration.redis_uri, 
+            configuration,
         )
         .await?;
 
@@ -90,48 +115,184 @@ pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    configuration: Settings,
 ) -> Result<Server, anyhow::Error> {
+    let Settings {
+        application,
+        redis_uri,
+        postmark_webhook: postmark_webhook_settings,
+        login_throttle: login_throttle_settings,
+        password_policy,
+        password_hashing,
+        session: session_settings,
+        idempotency: idempotency_settings,
+        metrics: metrics_settings,
+        ..
+    } = configuration;
+    let hmac_secret = application.hmac_secret;
     // Wrap db_pool into a smart pointer which is Arc
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let consent_text_version = web::Data::new(ConsentTextVersion(application.consent_text_version));
+    let cors_allowed_origins = application.cors_allowed_origins;
+    let trusted_proxies = web::Data::new(TrustedProxies(application.trusted_proxies));
+    let postmark_webhook_settings = web::Data::new(postmark_webhook_settings);
+    let password_policy = web::Data::new(password_policy);
+    let password_hashing = web::Data::new(password_hashing);
+    // Redis drops session state at the latest when the absolute timeout is reached,
+    // the idle timeout is enforced by `reject_anonymous_users`
+    let session_state_ttl =
+        Duration::seconds(session_settings.absolute_timeout().as_secs() as i64);
+    let session_settings = web::Data::new(session_settings);
+    let idempotency_settings = web::Data::new(idempotency_settings);
+    let metrics_settings = web::Data::new(metrics_settings);
     let secret_key =  Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_throttle = web::Data::new(LoginThrottle::new(&redis_uri, login_throttle_settings).await?);
 
     // Capture connection from surrouding environment
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(), 
-                secret_key.clone()
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .session_lifecycle(BrowserSession::default().state_ttl(session_state_ttl))
+                    .build()
+            )
+            .wrap(from_fn(record_http_metrics))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
             .service(
                 web::scope("/admin")
+                    // The last middleware registered runs first
+                    .wrap(from_fn(verify_csrf_token))
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/email", web::get().to(account_email_form))
+                    .route("/email", web::post().to(request_email_change))
+                    .route("/email/verify", web::get().to(verify_account_email))
                     .route("/logout", web::post().to(log_out))
+                    .route("/totp", web::get().to(totp_form))
+                    .route("/totp/enable", web::post().to(enable_two_factor))
+                    .route("/totp/disable", web::post().to(disable_two_factor))
+                    .route("/sessions", web::get().to(sessions_form))
+                    .route("/sessions/revoke", web::post().to(revoke_session))
+                    .route("/sessions/revoke_others", web::post().to(revoke_other_sessions))
+                    .route("/api_tokens", web::get().to(api_tokens_form))
+                    .route("/api_tokens", web::post().to(create_api_token))
+                    .route("/api_tokens/revoke", web::post().to(revoke_api_token))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers/{subscriber_id}", web::get().to(subscriber_details))
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(RequirePermission::new(Permission::PublishIssue))
+                            .route(web::get().to(publish_newsletter_form))
+                            .route(
+                                web::post()
+                                    .to(publish_newsletter)
+                                    .wrap(Idempotent::new().on_replay(send_newsletter_accepted_message)),
+                            )
+                    )
+                    .service(
+                        web::resource("/topics")
+                            .wrap(RequirePermission::new(Permission::PublishIssue))
+                            .route(web::get().to(topics_form))
+                            .route(web::post().to(create_topic))
+                    )
+                    .service(
+                        web::scope("/suppressions")
+                            .wrap(RequirePermission::new(Permission::ManageSubscribers))
+                            .route("", web::get().to(suppressions_form))
+                            .route("", web::post().to(add_suppression))
+                            .route("/remove", web::post().to(remove_suppressed_email))
+                            .route("/upload", web::post().to(upload_suppressions))
+                    )
+                    .service(
+                        web::scope("/data_requests")
+                            .wrap(RequirePermission::new(Permission::ManageSubscribers))
+                            .route("", web::get().to(data_requests_form))
+                            .route("/export", web::get().to(export_subscriber_data))
+                            .route("/erase", web::post().to(erase_subscriber_data))
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(RequirePermission::new(Permission::ManageUsers))
+                            .route("", web::get().to(list_users_form))
+                            .route("/invite", web::post().to(invite_user))
+                            .route("/role", web::post().to(change_user_role))
+                            .route("/deactivate", web::post().to(deactivate_user_account))
+                    )
+                    .service(
+                        web::scope("/webhooks")
+                            .wrap(RequirePermission::new(Permission::ManageWebhooks))
+                            .route("", web::get().to(webhooks_form))
+                            .route("", web::post().to(add_webhook))
+                            .route("/remove", web::post().to(remove_webhook))
+                    )
+            )
+            .route("/api/openapi.json", web::get().to(openapi_json))
+            .route("/api/docs", web::get().to(api_docs))
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .app_data(web::JsonConfig::default().error_handler(api_extractor_error))
+                    .app_data(web::PathConfig::default().error_handler(api_extractor_error))
+                    .app_data(web::QueryConfig::default().error_handler(api_extractor_error))
+                    .configure(|cfg| {
+                        for route in API_ROUTES {
+                            if let Some(path) = route.path.strip_prefix("/api/v1") {
+                                cfg.route(path, route.route());
+                            }
+                        }
+                    })
             )
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
-            .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/login/two_factor", web::get().to(two_factor_form))
+            .route("/login/two_factor", web::post().to(verify_two_factor))
+            .route("/password_reset", web::get().to(request_password_reset_form))
+            .route("/password_reset", web::post().to(request_password_reset))
+            .route("/password_reset/confirm", web::get().to(password_reset_form))
+            .route("/password_reset/confirm", web::post().to(reset_password_with_token))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
+            .configure(|cfg| {
+                // The `/api/v1` routes are registered in their scope above
+                for route in API_ROUTES {
+                    if route.path.starts_with("/api/v1/") {
+                        continue;
+                    }
+                    cfg.service(
+                        web::resource(route.path)
+                            // Only the subscription form is posted from other sites
+                            .wrap(Condition::new(
+                                route.path == "/subscriptions",
+                                subscriptions_cors(&cors_allowed_origins),
+                            ))
+                            .route(route.route())
+                    );
+                }
+            })
+            .route("/subscriptions/preferences", web::get().to(preferences_form))
+            .route("/subscriptions/preferences", web::post().to(save_preferences))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(consent_text_version.clone())
+            .app_data(postmark_webhook_settings.clone())
+            .app_data(login_throttle.clone())
+            .app_data(password_policy.clone())
+            .app_data(password_hashing.clone())
+            .app_data(session_settings.clone())
+            .app_data(idempotency_settings.clone())
+            .app_data(metrics_settings.clone())
+            .app_data(trusted_proxies.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
@@ -140,5 +301,113 @@ pub async fn run(
     Ok(server)
 }
 
+/// A route of the API, as described by the OpenAPI document of `ApiDoc`.
+pub struct ApiRoute {
+    pub method: Method,
+    pub path: &'static str,
+    handler: fn() -> Route,
+}
+
+impl ApiRoute {
+    fn route(&self) -> Route {
+        (self.handler)().method(self.method.clone())
+    }
+}
+
+/// Every route of the API. `run` registers them from this table, which
+/// lets the tests check the OpenAPI document against what is served.
+pub const API_ROUTES: &[ApiRoute] = &[
+    ApiRoute {
+        method: Method::GET,
+        path: "/health_check",
+        handler: || web::route().to(health_check),
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/metrics",
+        handler: || web::route().to(metrics),
+    },
+    ApiRoute {
+        method: Method::POST,
+        path: "/subscriptions",
+        handler: || web::route().to(subscribe),
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/subscriptions/confirm",
+        handler: || web::route().to(confirm),
+    },
+    ApiRoute {
+        method: Method::POST,
+        path: "/webhooks/postmark",
+        handler: || web::route().to(postmark_webhook),
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/api/v1/issues",
+        handler: || web::route().to(api_list_issues),
+    },
+    ApiRoute {
+        method: Method::POST,
+        path: "/api/v1/issues",
+        handler: || {
+            web::route()
+                .to(api_publish_issue)
+                .wrap(Idempotent::new().json())
+                .wrap(RequirePermission::new(Permission::PublishIssue).json())
+        },
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/api/v1/subscribers",
+        handler: || web::route().to(api_list_subscribers),
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/api/v1/subscribers/{subscriber_id}",
+        handler: || web::route().to(api_get_subscriber),
+    },
+    ApiRoute {
+        method: Method::DELETE,
+        path: "/api/v1/subscribers/{subscriber_id}",
+        handler: || {
+            web::route()
+                .to(api_unsubscribe_subscriber)
+                .wrap(RequirePermission::new(Permission::ManageSubscribers).json())
+        },
+    },
+    ApiRoute {
+        method: Method::GET,
+        path: "/api/v1/data_requests/export",
+        handler: || {
+            web::route()
+                .to(api_export_subscriber_data)
+                .wrap(RequirePermission::new(Permission::ManageSubscribers).json())
+        },
+    },
+    ApiRoute {
+        method: Method::POST,
+        path: "/api/v1/data_requests/erase",
+        handler: || {
+            web::route()
+                .to(api_erase_subscriber_data)
+                .wrap(RequirePermission::new(Permission::ManageSubscribers).json())
+        },
+    },
+];
+
+/// Let the configured sites subscribe visitors from the browser.
+/// Requests from other origins still go through, as plain form posts always
+/// did, but get no CORS headers so scripts cannot read the responses.
+fn subscriptions_cors(allowed_origins: &[String]) -> Cors {
+    allowed_origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .block_on_origin_mismatch(false)
+        .allowed_methods(["POST"])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(3600)
+}
+
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
\ No newline at end of file
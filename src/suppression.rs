@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool};
+
+//----------------------------------------------------------------
+/// Why an address ended up on the suppression list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionSource {
+    Manual,
+    Bounce,
+    Complaint,
+    Legal,
+}
+
+impl SuppressionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionSource::Manual => "manual",
+            SuppressionSource::Bounce => "bounce",
+            SuppressionSource::Complaint => "complaint",
+            SuppressionSource::Legal => "legal",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionSource {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.trim().to_lowercase().as_str() {
+            "manual" => Ok(Self::Manual),
+            "bounce" => Ok(Self::Bounce),
+            "complaint" => Ok(Self::Complaint),
+            "legal" => Ok(Self::Legal),
+            other => Err(format!(
+                "{} is not a supported suppression source. \
+                Use either `manual`, `bounce`, `complaint` or `legal`.",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SuppressedEmail {
+    pub email: String,
+    pub source: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+//----------------------------------------------------------------
+/// Returns the source of the suppression entry for `email`, if there is one.
+#[tracing::instrument(name = "Check suppression list", skip(executor))]
+pub async fn get_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<SuppressionSource>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT source FROM suppressed_emails WHERE email = lower($1)"#,
+        email
+    )
+    .fetch_optional(executor)
+    .await?;
+    row.map(|r| SuppressionSource::try_from(r.source).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+/// Add `email` to the suppression list.
+/// An existing `legal` entry is never downgraded.
+#[tracing::instrument(name = "Suppress email", skip(executor))]
+pub async fn suppress_email(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    source: SuppressionSource,
+    reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, source, reason, created_at)
+        VALUES (lower($1), $2, $3, now())
+        ON CONFLICT (email) DO UPDATE
+        SET source = EXCLUDED.source, reason = EXCLUDED.reason
+        WHERE suppressed_emails.source <> 'legal'
+        "#,
+        email,
+        source.as_str(),
+        reason,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Remove email from suppression list", skip(executor))]
+pub async fn remove_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"DELETE FROM suppressed_emails WHERE email = lower($1)"#,
+        email
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows > 0)
+}
+
+#[tracing::instrument(name = "List suppressed emails", skip(db_pool))]
+pub async fn list_suppressions(db_pool: &PgPool) -> Result<Vec<SuppressedEmail>, sqlx::Error> {
+    sqlx::query_as!(
+        SuppressedEmail,
+        r#"
+        SELECT email, source, reason, created_at
+        FROM suppressed_emails
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
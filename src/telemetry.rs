@@ -1,7 +1,15 @@
+use crate::configuration::OtlpSettings;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use std::collections::HashMap;
 use tokio::task::JoinHandle;
-use tracing::{subscriber::set_global_default, Subscriber};
+use tracing::{subscriber::set_global_default, Span, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 //----------------------------------------------------------------
@@ -13,31 +21,75 @@ use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 /// We need to explicitly call out that the returned subscriber is
 /// `Send` and `Sync` to make it possible to pass it to `init_subscriber`
 /// later on.
-
+/// Spans are also exported to an OpenTelemetry collector when `otlp` is
+/// set, which needs a Tokio runtime to be running.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    otlp: Option<&OtlpSettings>,
 ) -> impl Subscriber + Send + Sync
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let otel_layer = otlp.map(|settings| {
+        tracing_opentelemetry::layer().with_tracer(otlp_tracer(name.clone(), settings))
+    });
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
     Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otel_layer)
+}
+
+fn otlp_tracer(service_name: String, settings: &OtlpSettings) -> trace::Tracer {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&settings.endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                service_name,
+            )])),
+        )
+        .install_batch(runtime::Tokio)
+        .expect("Failed to install the OTLP trace exporter")
 }
 
 // Register a subscriber as global default to process span data.
 // It should only be called once!
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set log tracer");
+    // `TracingLogger` picks up the trace context sent by callers with it
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
     set_global_default(subscriber).expect("Failed to set global default");
 }
 
+//----------------------------------------------------------------
+const TRACEPARENT: &str = "traceparent";
+
+/// The W3C `traceparent` of the current span, to continue its trace in work
+/// done later on, e.g. by a background worker.
+/// `None` when traces are not exported.
+pub fn current_traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// Make `span` part of the trace `traceparent` was taken from.
+pub fn set_parent_from_traceparent(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    span.set_parent(TraceContextPropagator::new().extract(&carrier));
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
@@ -46,3 +98,43 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{current_traceparent, set_parent_from_traceparent};
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn spans_join_the_trace_of_a_saved_traceparent() {
+        // Tracers only hold a weak reference to their provider
+        let provider = TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::with_default(subscriber, || {
+            let traceparent = tracing::info_span!("Publish an issue")
+                .in_scope(current_traceparent)
+                .expect("Spans are exported, they have a trace context");
+
+            let worker_span = tracing::info_span!("Deliver an issue");
+            set_parent_from_traceparent(&worker_span, &traceparent);
+            let trace_id = worker_span.in_scope(|| {
+                let child = tracing::info_span!("Send an email");
+                child.context().span().span_context().trace_id()
+            });
+
+            assert!(traceparent.contains(&trace_id.to_string()));
+        });
+    }
+
+    #[test]
+    fn there_is_no_traceparent_when_spans_are_not_exported() {
+        tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+            let traceparent = tracing::info_span!("Publish an issue").in_scope(current_traceparent);
+            assert!(traceparent.is_none());
+        });
+    }
+}
@@ -0,0 +1,163 @@
+use crate::configuration::SessionSettings;
+use crate::utils::client_ip;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+// Longest user agent we keep, they can be arbitrarily long.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+//----------------------------------------------------------------
+/// Where a login came from.
+pub struct SessionMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SessionMetadata {
+    pub fn from_request(request: &HttpRequest) -> Self {
+        let ip_address = client_ip(request);
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        Self {
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+//----------------------------------------------------------------
+/// Record a new login and return the id to store in the session.
+#[tracing::instrument(name = "Create user session", skip(db_pool, metadata))]
+pub async fn create_user_session(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    metadata: &SessionMetadata,
+) -> Result<Uuid, anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (
+            session_id, user_id, created_at, last_seen_at, ip_address, user_agent
+        )
+        VALUES ($1, $2, now(), now(), $3, $4)
+        "#,
+        session_id,
+        user_id,
+        metadata.ip_address,
+        metadata.user_agent,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the user session.")?;
+    Ok(session_id)
+}
+
+/// Mark the session as seen now.
+/// Returns `false` if it does not exist or has been revoked.
+#[tracing::instrument(name = "Touch user session", skip(db_pool))]
+pub async fn touch_user_session(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET last_seen_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id,
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to update the user session.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Sessions that are neither revoked nor timed out.
+#[tracing::instrument(name = "List user sessions", skip(db_pool, settings))]
+pub async fn list_user_sessions(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    settings: &SessionSettings,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM user_sessions
+        WHERE user_id = $1
+        AND revoked_at IS NULL
+        AND last_seen_at > now() - make_interval(mins => $2)
+        AND created_at > now() - make_interval(hours => $3)
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        settings.idle_timeout_minutes as i32,
+        settings.absolute_timeout_hours as i32,
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve user sessions.")
+}
+
+/// Returns `false` if the session is not one of the user's active sessions.
+#[tracing::instrument(name = "Revoke user session", skip(executor))]
+pub async fn revoke_user_session(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the user session.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Revoke every session of the user except `keep`, if any.
+#[tracing::instrument(name = "Revoke other user sessions", skip(executor))]
+pub async fn revoke_other_user_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions SET revoked_at = now()
+        WHERE user_id = $1
+        AND revoked_at IS NULL
+        AND session_id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the user sessions.")?
+    .rows_affected();
+    Ok(n_updated_rows)
+}
@@ -0,0 +1,387 @@
+use crate::authentication::Role;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// How long an invitation link stays valid.
+const INVITATION_TTL_DAYS: i64 = 7;
+// How long a link to verify a new account email stays valid.
+const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+
+//----------------------------------------------------------------
+pub struct PendingInvitation {
+    pub email: String,
+    pub role: Role,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct UserSummary {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub is_active: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AcceptInvitationError {
+    #[error("This invitation is invalid or has expired.")]
+    InvalidInvitation,
+    #[error("The username {0} is already taken.")]
+    UsernameTaken(String),
+    #[error("{0} already belongs to another account.")]
+    EmailTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum VerifyEmailError {
+    #[error("This verification link is invalid or has expired.")]
+    InvalidToken,
+    #[error("{0} already belongs to another account.")]
+    EmailTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+//----------------------------------------------------------------
+/// Store a new invitation and return the token to embed in the invite link.
+/// Only its hash is persisted, as for password reset tokens.
+#[tracing::instrument(name = "Create user invitation", skip(db_pool))]
+pub async fn create_invitation(
+    db_pool: &PgPool,
+    email: &str,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<String, sqlx::Error> {
+    let invitation_token = generate_invitation_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (
+            token_hash, email, role, invited_by, created_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, now(), $5)
+        "#,
+        hash_invitation_token(&invitation_token),
+        email,
+        role.as_str(),
+        invited_by,
+        Utc::now() + Duration::days(INVITATION_TTL_DAYS),
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(invitation_token)
+}
+
+/// Returns the invitation behind `invitation_token` if it can still be accepted.
+#[tracing::instrument(name = "Get pending invitation", skip(db_pool, invitation_token))]
+pub async fn get_pending_invitation(
+    db_pool: &PgPool,
+    invitation_token: &str,
+) -> Result<Option<PendingInvitation>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > now()
+        "#,
+        hash_invitation_token(invitation_token)
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the invitation.")?;
+    row.map(|r| {
+        let role = Role::try_from(r.role).map_err(anyhow::Error::msg)?;
+        Ok(PendingInvitation {
+            email: r.email,
+            role,
+            expires_at: r.expires_at,
+        })
+    })
+    .transpose()
+}
+
+/// Create the invited user and mark the invitation as used, atomically.
+#[tracing::instrument(name = "Create invited user", skip(db_pool, invitation_token, password_hash))]
+pub async fn create_invited_user(
+    db_pool: &PgPool,
+    invitation_token: &str,
+    username: &str,
+    password_hash: Secret<String>,
+) -> Result<Uuid, AcceptInvitationError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let token_hash = hash_invitation_token(invitation_token);
+    // Lock the invitation so that it cannot be used twice
+    let invitation = sqlx::query!(
+        r#"
+        SELECT email, role
+        FROM user_invitations
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > now()
+        FOR UPDATE
+        "#,
+        token_hash
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the invitation.")?
+    .ok_or(AcceptInvitationError::InvalidInvitation)?;
+
+    if username_exists(&mut transaction, username).await? {
+        return Err(AcceptInvitationError::UsernameTaken(username.to_owned()));
+    }
+    // Another invitation to the same address may have been accepted, or a
+    // user may have claimed it, since this one was sent
+    if email_exists(&mut transaction, &invitation.email).await? {
+        return Err(AcceptInvitationError::EmailTaken(invitation.email));
+    }
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role, email)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        invitation.role,
+        invitation.email,
+    )
+    .execute(&mut transaction)
+    .await
+    // The checks above race with concurrent requests, the constraints do not
+    .map_err(|e| match unique_violation(&e) {
+        Some("users_username_key") => AcceptInvitationError::UsernameTaken(username.to_owned()),
+        Some("users_email_key") => AcceptInvitationError::EmailTaken(invitation.email.clone()),
+        _ => anyhow::Error::new(e)
+            .context("Failed to store the new user.")
+            .into(),
+    })?;
+    sqlx::query!(
+        r#"UPDATE user_invitations SET accepted_at = now() WHERE token_hash = $1"#,
+        token_hash
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the invitation as accepted.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation.")?;
+    Ok(user_id)
+}
+
+async fn username_exists(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE username = $1"#,
+        username
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to check whether the username is taken.")?;
+    Ok(row.is_some())
+}
+
+async fn email_exists(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to check whether the email is taken.")?;
+    Ok(row.is_some())
+}
+
+/// The name of the unique constraint `e` violates, if any.
+fn unique_violation(e: &sqlx::Error) -> Option<&str> {
+    match e {
+        sqlx::Error::Database(e) if e.code().as_deref() == Some("23505") => e.constraint(),
+        _ => None,
+    }
+}
+//----------------------------------------------------------------
+#[tracing::instrument(name = "List users", skip(db_pool))]
+pub async fn list_users(db_pool: &PgPool) -> Result<Vec<UserSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSummary,
+        r#"
+        SELECT user_id, username, email, role, is_active
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+#[tracing::instrument(name = "Check whether an email belongs to a user", skip(db_pool))]
+pub async fn user_email_exists(db_pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "Get user email", skip(db_pool))]
+pub async fn get_user_email(db_pool: &PgPool, user_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, user_id)
+        .fetch_optional(db_pool)
+        .await?;
+    Ok(row.and_then(|r| r.email))
+}
+
+/// Store a request to change the email of a user and return the token to
+/// embed in the verification link sent to the new address.
+/// Only its hash is persisted, as for password reset tokens.
+#[tracing::instrument(name = "Create email verification token", skip(db_pool, email))]
+pub async fn create_email_verification_token(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    email: &str,
+) -> Result<Secret<String>, sqlx::Error> {
+    let token = generate_email_verification_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_verification_tokens (token_hash, user_id, email, created_at, expires_at)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        hash_email_verification_token(&token),
+        user_id,
+        email,
+        Utc::now() + Duration::hours(EMAIL_VERIFICATION_TTL_HOURS),
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(Secret::new(token))
+}
+
+/// Consume `token` and make the address it was sent to the email of the
+/// user. The token only works for the user who asked for it.
+/// Returns the new email.
+#[tracing::instrument(name = "Verify user email", skip(db_pool, token))]
+pub async fn verify_user_email(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    token: &str,
+) -> Result<String, VerifyEmailError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let email = sqlx::query!(
+        r#"
+        DELETE FROM email_verification_tokens
+        WHERE token_hash = $1 AND user_id = $2 AND expires_at > now()
+        RETURNING email
+        "#,
+        hash_email_verification_token(token),
+        user_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to consume the email verification token.")?
+    .ok_or(VerifyEmailError::InvalidToken)?
+    .email;
+    // Someone else may have claimed the address since the link was sent
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users SET email = $2
+        WHERE user_id = $1
+        AND NOT EXISTS (
+            SELECT 1 FROM users WHERE lower(email) = lower($2) AND user_id <> $1
+        )
+        "#,
+        user_id,
+        email,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the new email.")?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Err(VerifyEmailError::EmailTaken(email));
+    }
+    // Any other pending change is now pointless
+    sqlx::query!(
+        r#"DELETE FROM email_verification_tokens WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete outstanding email verification tokens.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to verify an email.")?;
+    Ok(email)
+}
+
+/// Returns `false` if there is no user with this id.
+#[tracing::instrument(name = "Change user role", skip(db_pool))]
+pub async fn set_user_role(db_pool: &PgPool, user_id: Uuid, role: Role) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"UPDATE users SET role = $2 WHERE user_id = $1"#,
+        user_id,
+        role.as_str()
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Returns `false` if there is no user with this id.
+#[tracing::instrument(name = "Deactivate user", skip(db_pool))]
+pub async fn deactivate_user(db_pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"UPDATE users SET is_active = false WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+/// Generate a random 25-characters-long case-sensitive invitation token.
+fn generate_invitation_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
+
+fn hash_invitation_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn hash_email_verification_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a random 32-characters-long case-sensitive verification token.
+fn generate_email_verification_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
@@ -1,5 +1,7 @@
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::net::IpAddr;
 
 // Return an opaque 500 while preserving the error's root cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -21,4 +23,133 @@ pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
-}
\ No newline at end of file
+}
+
+// The reverse proxies of `ApplicationSettings::trusted_proxies`.
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+// The address of the client, as far as we can tell.
+// Anybody can send `X-Forwarded-For`: it is only read when the request comes
+// from one of our proxies, and then each proxy appends the address it got the
+// request from, so the client is the right-most address that is not a proxy.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let trusted_proxies = request
+        .app_data::<web::Data<TrustedProxies>>()
+        .map(|t| t.0.as_slice())
+        .unwrap_or_default();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer.to_string());
+    }
+    let forwarded_for: Vec<&str> = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .map(str::trim)
+        .collect();
+    let client = forwarded_for
+        .into_iter()
+        .rev()
+        .map_while(|address| address.parse::<IpAddr>().ok())
+        .find(|address| !trusted_proxies.contains(address))
+        .unwrap_or(peer);
+    Some(client.to_string())
+}
+
+// The body of every error returned by the JSON API.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+pub fn json_error(status_code: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status_code).json(ErrorBody {
+        error: message.to_owned(),
+    })
+}
+
+// Don't leak how much of a secret was right through timing.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+// Only redirect back to pages of the admin area after logging in,
+// never to another site.
+pub fn is_admin_location(location: &str) -> bool {
+    let in_admin_area = location == "/admin"
+        || location.starts_with("/admin/")
+        || location.starts_with("/admin?");
+    in_admin_area && !location.contains("//") && !location.contains('\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_ip, constant_time_eq, is_admin_location, TrustedProxies};
+    use actix_web::test::TestRequest;
+    use actix_web::web;
+
+    const PROXY: &str = "10.0.0.1:443";
+
+    fn request(peer: &str, forwarded_for: &str) -> TestRequest {
+        TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .insert_header(("X-Forwarded-For", forwarded_for))
+            .app_data(web::Data::new(TrustedProxies(vec!["10.0.0.1".parse().unwrap()])))
+    }
+
+    #[test]
+    fn admin_pages_are_allowed() {
+        assert!(is_admin_location("/admin/dashboard"));
+        assert!(is_admin_location("/admin/subscribers?page=2"));
+    }
+
+    #[test]
+    fn anything_else_is_rejected() {
+        for location in [
+            "https://evil.example.com/admin/",
+            "//evil.example.com/admin/",
+            "/administrator",
+            "/admin//evil.example.com",
+            "/admin/\\evil.example.com",
+            "/login",
+            "",
+        ] {
+            assert!(!is_admin_location(location), "{} was allowed", location);
+        }
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_when_not_sent_by_a_trusted_proxy() {
+        let request = request("203.0.113.7:5000", "198.51.100.1").to_http_request();
+        assert_eq!(client_ip(&request).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_client_is_the_address_our_proxy_got_the_request_from() {
+        // The client made up the first address, our proxy appended the second
+        let request = request(PROXY, "198.51.100.1, 203.0.113.7").to_http_request();
+        assert_eq!(client_ip(&request).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_proxy_is_the_client_without_forwarded_for() {
+        let request = TestRequest::default()
+            .peer_addr(PROXY.parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies(vec!["10.0.0.1".parse().unwrap()])))
+            .to_http_request();
+        assert_eq!(client_ip(&request).as_deref(), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn secrets_are_compared_in_full() {
+        assert!(constant_time_eq("abc123", "abc123"));
+        assert!(!constant_time_eq("abc123", "abc124"));
+        assert!(!constant_time_eq("abc123", "abc1234"));
+        assert!(!constant_time_eq("", "abc123"));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::outbound_webhooks::sign_webhook_payload;
+use crate::{configuration::Settings, startup::get_connection_pool};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+pub const WEBHOOK_ID_HEADER: &str = "X-Webhook-Id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+// With the backoff below, the last attempt happens about an hour after the
+// event.
+pub const MAX_DELIVERY_ATTEMPTS: i16 = 8;
+
+pub async fn run_webhook_worker_until_stopped(
+    configuration: Settings
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database)
+            .await
+            .expect("Failed to connect to Postgres.");
+    worker_loop(connection_pool, webhook_http_client()).await
+}
+
+/// Endpoints are outside of our control, do not wait on them for long.
+pub fn webhook_http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to build the webhook HTTP client")
+}
+
+async fn worker_loop(
+    db_pool: PgPool,
+    http_client: reqwest::Client,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_deliver_webhook(&db_pool, &http_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+struct PendingDelivery {
+    webhook_event_id: Uuid,
+    webhook_endpoint_id: Uuid,
+    n_attempts: i16,
+    event_type: String,
+    payload: String,
+    occurred_at: DateTime<Utc>,
+    url: String,
+    secret: String,
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        webhook_event_id=tracing::field::Empty,
+        webhook_endpoint_id=tracing::field::Empty,
+    ),
+    err
+)]
+pub async fn try_deliver_webhook(
+    db_pool: &PgPool,
+    http_client: &reqwest::Client,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, delivery)) = dequeue_delivery(db_pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("webhook_event_id", display(delivery.webhook_event_id))
+        .record("webhook_endpoint_id", display(delivery.webhook_endpoint_id));
+    let body = serde_json::json!({
+        "id": delivery.webhook_event_id,
+        "type": delivery.event_type,
+        "occurred_at": delivery.occurred_at,
+        "data": serde_json::from_str::<serde_json::Value>(&delivery.payload)?,
+    })
+    .to_string();
+    let timestamp = Utc::now().timestamp();
+    let outcome = http_client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header(WEBHOOK_ID_HEADER, delivery.webhook_event_id.to_string())
+        .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+        .header(
+            WEBHOOK_SIGNATURE_HEADER,
+            sign_webhook_payload(&delivery.secret, timestamp, &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match outcome {
+        Ok(response) => {
+            mark_delivered(&mut transaction, &delivery, response.status().as_u16()).await?;
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                n_attempts = delivery.n_attempts + 1,
+                "Failed to deliver a webhook event.",
+            );
+            record_failed_attempt(&mut transaction, &delivery, &e).await?;
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_delivery(
+    db_pool: &PgPool,
+) -> Result<Option<(PgTransaction, PendingDelivery)>, anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    let delivery = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT
+            d.webhook_event_id, d.webhook_endpoint_id, d.n_attempts,
+            e.event_type, e.payload, e.occurred_at, w.url, w.secret
+        FROM webhook_deliveries d
+        JOIN webhook_events e ON e.webhook_event_id = d.webhook_event_id
+        JOIN webhook_endpoints w ON w.webhook_endpoint_id = d.webhook_endpoint_id
+        WHERE d.status = 'pending' AND d.execute_after <= now()
+        FOR UPDATE OF d
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    Ok(delivery.map(|d| (transaction, d)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn mark_delivered(
+    transaction: &mut PgTransaction,
+    delivery: &PendingDelivery,
+    response_status: u16,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET
+            status = 'delivered',
+            n_attempts = n_attempts + 1,
+            last_attempt_at = now(),
+            last_response_status = $3,
+            last_error = NULL
+        WHERE webhook_event_id = $1 AND webhook_endpoint_id = $2
+        "#,
+        delivery.webhook_event_id,
+        delivery.webhook_endpoint_id,
+        response_status as i16,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Schedule another attempt, or give up once `MAX_DELIVERY_ATTEMPTS` is
+/// reached.
+#[tracing::instrument(skip_all)]
+async fn record_failed_attempt(
+    transaction: &mut PgTransaction,
+    delivery: &PendingDelivery,
+    error: &reqwest::Error,
+) -> Result<(), anyhow::Error> {
+    let n_attempts = delivery.n_attempts + 1;
+    let status = if n_attempts >= MAX_DELIVERY_ATTEMPTS {
+        "failed"
+    } else {
+        "pending"
+    };
+    let execute_after = Utc::now() + retry_backoff(n_attempts);
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET
+            status = $3,
+            n_attempts = $4,
+            execute_after = $5,
+            last_attempt_at = now(),
+            last_response_status = $6,
+            last_error = $7
+        WHERE webhook_event_id = $1 AND webhook_endpoint_id = $2
+        "#,
+        delivery.webhook_event_id,
+        delivery.webhook_endpoint_id,
+        status,
+        n_attempts,
+        execute_after,
+        error.status().map(|s| s.as_u16() as i16),
+        error.to_string(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Exponential backoff: 30 seconds after the first failed attempt, then
+/// twice as long every time.
+fn retry_backoff(n_attempts: i16) -> chrono::Duration {
+    let exponent = (n_attempts.max(1) - 1).min(10) as u32;
+    chrono::Duration::seconds(30 * 2_i64.pow(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_backoff;
+
+    #[test]
+    fn the_backoff_doubles_after_every_failed_attempt() {
+        assert_eq!(retry_backoff(1).num_seconds(), 30);
+        assert_eq!(retry_backoff(2).num_seconds(), 60);
+        assert_eq!(retry_backoff(7).num_seconds(), 1920);
+    }
+}
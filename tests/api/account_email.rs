@@ -0,0 +1,140 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const NEW_EMAIL: &str = "admin@example.com";
+
+async fn stored_email(test_app: &TestApp) -> Option<String> {
+    sqlx::query!(
+        "SELECT email FROM users WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .email
+}
+
+/// Ask for `NEW_EMAIL` to become the email of the test user and return
+/// the verification link sent to it.
+async fn request_email_change(test_app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_account_email(NEW_EMAIL).await;
+    assert_is_redirect_to(&response, "/admin/email");
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], NEW_EMAIL);
+    test_app.get_confirmation_link(&email_request).html
+}
+
+#[tokio::test]
+async fn a_user_without_an_email_can_add_one_and_reset_their_password() {
+    // Like the seeded admin, the test user starts without an email
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    assert!(test_app
+        .get_account_email_html()
+        .await
+        .contains("Your account has no email address yet"));
+
+    let verification_link = request_email_change(&test_app).await;
+    let response = test_app
+        .api_client
+        .get(verification_link)
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/email");
+    assert!(test_app
+        .get_account_email_html()
+        .await
+        .contains("admin@example.com is now the email of your account."));
+    assert_eq!(stored_email(&test_app).await.as_deref(), Some(NEW_EMAIL));
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.post_logout().await;
+    test_app.post_password_reset_request(NEW_EMAIL).await;
+    // The verification email, then the reset link
+    test_app.wait_for_emails(2).await;
+}
+
+#[tokio::test]
+async fn the_email_is_not_changed_until_the_link_is_followed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    request_email_change(&test_app).await;
+
+    assert_eq!(stored_email(&test_app).await, None);
+}
+
+#[tokio::test]
+async fn a_verification_link_only_works_for_the_user_who_asked_for_it() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let verification_link = request_email_change(&test_app).await;
+    test_app.post_logout().await;
+    let other_user = TestUser::generate();
+    other_user.store(&test_app.db_pool).await;
+    other_user.login(&test_app).await;
+
+    test_app
+        .api_client
+        .get(verification_link)
+        .send()
+        .await
+        .unwrap();
+
+    assert!(test_app
+        .get_account_email_html()
+        .await
+        .contains("This verification link is invalid or has expired."));
+    assert_eq!(stored_email(&test_app).await, None);
+}
+
+#[tokio::test]
+async fn the_email_of_another_account_cannot_be_claimed() {
+    let test_app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&test_app.db_pool).await;
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        NEW_EMAIL,
+        other_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.test_user.login(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_account_email("Admin@Example.com").await;
+
+    assert_is_redirect_to(&response, "/admin/email");
+    assert!(test_app
+        .get_account_email_html()
+        .await
+        .contains("Admin@Example.com already belongs to an account."));
+}
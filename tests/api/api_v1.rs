@@ -0,0 +1,388 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::Method;
+use uuid::Uuid;
+
+async fn insert_confirmed_subscriber(test_app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, preferences_token)
+        VALUES ($1, $2, 'Subscriber', now(), 'confirmed', $3)
+        "#,
+        subscriber_id,
+        email,
+        Uuid::new_v4().to_string(),
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .expect("Failed to insert a subscriber.");
+    subscriber_id
+}
+
+fn issue_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let test_app = spawn_app().await;
+
+    for token in ["", "nlt_not-a-real-token"] {
+        let response = test_app
+            .api_request(Method::GET, "/issues", token)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn the_old_unauthenticated_publish_route_is_gone() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(&format!("{}/newsletters", &test_app.address))
+        .form(&[("title", "Newsletter title")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn tokens_are_shown_once_and_stored_hashed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let token = test_app.create_api_token().await;
+
+    assert!(token.starts_with("nlt_"));
+    let html = test_app.get_api_tokens_html().await;
+    assert!(html.contains("Test token"));
+    assert!(!html.contains(&token));
+    let row = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(row.token_hash, token);
+}
+
+#[tokio::test]
+async fn an_issue_can_be_published_and_listed_with_a_token() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let response = test_app
+        .api_request(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    let issues: serde_json::Value = test_app
+        .api_request(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issues[0]["newsletter_issue_id"], issue_id.as_str());
+    assert_eq!(issues[0]["title"], "Newsletter title");
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent_per_key() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let mut bodies = Vec::new();
+    for _ in 0..2 {
+        let response = test_app
+            .api_request(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&issue_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+        bodies.push(response.text().await.unwrap());
+    }
+
+    assert_eq!(bodies[0], bodies[1]);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+}
+
+#[tokio::test]
+async fn invalid_publish_requests_get_a_json_error() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let test_cases = vec![
+        (None, issue_body(), "missing idempotency key"),
+        (
+            Some(Uuid::new_v4().to_string()),
+            serde_json::json!({"title": "Newsletter title"}),
+            "missing content",
+        ),
+        (
+            Some(Uuid::new_v4().to_string()),
+            serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "topic_id": Uuid::new_v4(),
+            }),
+            "unknown topic",
+        ),
+    ];
+
+    for (idempotency_key, body, description) in test_cases {
+        let mut request = test_app.api_request(Method::POST, "/issues", &token).json(&body);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        let response = request.send().await.unwrap();
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject the request with {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string(), "No JSON error for {}.", description);
+    }
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_through_the_api() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "viewer").await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let response = test_app
+        .api_request(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_and_unsubscribed() {
+    let test_app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let subscribers: serde_json::Value = test_app
+        .api_request(Method::GET, "/subscribers?status=confirmed", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(subscribers[0]["email"], "ursula@example.com");
+
+    let response = test_app
+        .api_request(Method::DELETE, &format!("/subscribers/{}", subscriber_id), &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    let subscriber: serde_json::Value = test_app
+        .api_request(Method::GET, &format!("/subscribers/{}", subscriber_id), &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(subscriber["status"], "unsubscribed");
+}
+
+#[tokio::test]
+async fn unknown_subscribers_are_not_found() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let response = test_app
+        .api_request(Method::GET, &format!("/subscribers/{}", Uuid::new_v4()), &token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+
+    test_app.post_revoke_api_token(api_token_id).await;
+
+    let response = test_app
+        .api_request(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let html = test_app.get_api_tokens_html().await;
+    assert!(html.contains("The API token has been revoked."));
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    sqlx::query!(
+        "UPDATE users SET is_active = false WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app
+        .api_request(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscriber_data_can_be_exported_and_erased_with_a_token() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let response = test_app
+        .api_request(Method::GET, "/data_requests/export?email=ursula@example.com", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let dossier: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(dossier["email"], "ursula@example.com");
+    assert_eq!(dossier["subscriptions"][0]["status"], "confirmed");
+
+    let response = test_app
+        .api_request(Method::POST, "/data_requests/erase", &token)
+        .json(&serde_json::json!({ "email": "ursula@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["subscriptions"], 1);
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriptions.is_empty());
+}
+
+#[tokio::test]
+async fn viewers_cannot_export_or_erase_subscriber_data() {
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app.test_user.set_role(&test_app.db_pool, "viewer").await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let export = test_app
+        .api_request(Method::GET, "/data_requests/export?email=ursula@example.com", &token)
+        .send()
+        .await
+        .unwrap();
+    let erase = test_app
+        .api_request(Method::POST, "/data_requests/erase", &token)
+        .json(&serde_json::json!({ "email": "ursula@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    for response in [export, erase] {
+        assert_eq!(response.status().as_u16(), 403);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriptions.len(), 1);
+}
+
+#[tokio::test]
+async fn invalid_data_requests_get_a_json_error() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+
+    let export = test_app
+        .api_request(Method::GET, "/data_requests/export?email=not-an-email", &token)
+        .send()
+        .await
+        .unwrap();
+    let erase = test_app
+        .api_request(Method::POST, "/data_requests/erase", &token)
+        .json(&serde_json::json!({ "email": " " }))
+        .send()
+        .await
+        .unwrap();
+
+    for response in [export, erase] {
+        assert_eq!(response.status().as_u16(), 400);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+#[tokio::test]
+async fn a_viewer_cannot_publish_a_newsletter() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "viewer").await;
+    test_app.test_user.login(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("You do not have permission to perform this action."));
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn an_editor_can_publish_a_newsletter() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "editor").await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body())
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn a_viewer_cannot_manage_subscribers() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "viewer").await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_suppression(&serde_json::json!({
+            "email": "sangkhuudev@gmail.com",
+            "source": "manual",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = test_app.get_data_request_export("sangkhuudev@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_dashboard_only_links_to_permitted_pages() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "viewer").await;
+    test_app.test_user.login(&test_app).await;
+
+    let html_page = test_app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains(r#"<a href="/admin/subscribers">"#));
+    assert!(!html_page.contains(r#"<a href="/admin/newsletters">"#));
+    assert!(!html_page.contains(r#"<a href="/admin/users">"#));
+}
@@ -52,6 +52,30 @@ async fn new_password_fields_must_match() {
     ));
 }
 
+#[tokio::test]
+async fn new_password_must_follow_the_password_policy() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_change_password(&serde_json::json!({
+        "current_password": &test_app.test_user.password,
+        "new_password": "letmein",
+        "new_password_check": "letmein",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // Every failed rule gets its own message
+    let html_page = test_app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>The new password must be at least 12 characters long.</i></p>"
+    ));
+    assert!(html_page.contains(
+        "<p><i>The new password is too common - please choose another one.</i></p>"
+    ));
+}
+
 #[tokio::test]
 async fn current_password_must_be_valid() {
     let test_app = spawn_app().await;
@@ -0,0 +1,92 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn post_topic_with(test_app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    test_app
+        .api_client
+        .post(&format!("{}/admin/topics", &test_app.address))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn admin_forms_embed_the_csrf_token() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let token = test_app.csrf_token().await;
+
+    assert_eq!(token.len(), 64);
+    let html = test_app.get_change_password_html().await;
+    assert!(html.contains(&format!(r#"name="csrf_token" value="{token}""#)));
+}
+
+#[tokio::test]
+async fn a_post_without_a_csrf_token_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = post_topic_with(&test_app, &[("name", "Rust")]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let topics = sqlx::query!("SELECT name FROM topics")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(topics.is_empty());
+}
+
+#[tokio::test]
+async fn a_post_with_a_wrong_csrf_token_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.csrf_token().await;
+    let forged = "0".repeat(token.len());
+
+    let response = post_topic_with(&test_app, &[("name", "Rust"), ("csrf_token", &forged)]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_post_with_the_token_in_the_form_is_accepted() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.csrf_token().await;
+
+    let response = post_topic_with(&test_app, &[("name", "Rust"), ("csrf_token", &token)]).await;
+
+    assert_is_redirect_to(&response, "/admin/topics");
+}
+
+#[tokio::test]
+async fn a_token_in_the_query_string_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.csrf_token().await;
+
+    let response = test_app
+        .api_client
+        .post(&format!("{}/admin/topics", &test_app.address))
+        .query(&[("csrf_token", &token)])
+        .form(&[("name", "Rust")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_csrf_token_changes_when_logging_in_again() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let first_token = test_app.csrf_token().await;
+    test_app.post_logout().await;
+
+    test_app.test_user.login(&test_app).await;
+    let response = post_topic_with(&test_app, &[("name", "Rust"), ("csrf_token", &first_token)]).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,135 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscriber_data() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .get_data_request_export("sangkhuudev@gmail.com")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn export_returns_everything_stored_about_an_email() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .get_data_request_export("sangkhuudev@gmail.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let dossier: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(dossier["email"], "sangkhuudev@gmail.com");
+    assert_eq!(dossier["subscriptions"][0]["name"], "sang khuu");
+    assert_eq!(dossier["subscriptions"][0]["status"], "pending_confirmation");
+    assert_eq!(dossier["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(dossier["consent_events"][0]["event_type"], "subscribed");
+}
+
+#[tokio::test]
+async fn erasure_deletes_subscriber_and_tokens() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_data_request_erase(&serde_json::json!({
+            "email": "sangkhuudev@gmail.com",
+            "email_check": "sangkhuudev@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/data_requests");
+
+    let html_page = test_app.get_data_requests_html().await;
+    assert!(html_page.contains(
+        "<p><i>All data stored for sangkhuudev@gmail.com has been erased.</i></p>"
+    ));
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriptions.is_empty());
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn erasure_requires_matching_email_confirmation() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_data_request_erase(&serde_json::json!({
+            "email": "sangkhuudev@gmail.com",
+            "email_check": "someone-else@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/data_requests");
+
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriptions.len(), 1);
+}
+
+#[tokio::test]
+async fn export_includes_webhook_events_about_the_email() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    let response = test_app
+        .get_data_request_export("sangkhuudev@gmail.com")
+        .await;
+
+    let dossier: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(dossier["webhook_events"][0]["event_type"], "subscriber.subscribed");
+    let payload = dossier["webhook_events"][0]["payload"].as_str().unwrap();
+    assert!(payload.contains("sangkhuudev@gmail.com"));
+}
+
+#[tokio::test]
+async fn erasure_deletes_webhook_events_about_the_email() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app
+        .post_data_request_erase(&serde_json::json!({
+            "email": "SangKhuuDev@gmail.com",
+            "email_check": "SangKhuuDev@gmail.com",
+        }))
+        .await;
+
+    let events = sqlx::query!("SELECT payload FROM webhook_events")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+    let deliveries = sqlx::query!("SELECT status FROM webhook_deliveries")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(deliveries.is_empty());
+}
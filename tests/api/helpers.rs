@@ -1,14 +1,19 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
-use email_newsletter::configuration::{get_configuration, DatabaseSettings};
+use email_newsletter::configuration::{
+    get_configuration, DatabaseSettings, MetricsSettings, PostmarkWebhookSettings, Settings,
+};
 use email_newsletter::startup::{get_connection_pool, Application};
 use email_newsletter::telemetry::{get_subscriber, init_subscriber};
 use email_newsletter::email_client::EmailClient;
 use email_newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use email_newsletter::webhook_delivery_worker::{try_deliver_webhook, webhook_http_client};
 use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 //----------------------------------------------------------------
@@ -16,10 +21,12 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber =
+            get_subscriber(subscriber_name, default_filter_level, std::io::stdout, None);
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber =
+            get_subscriber(subscriber_name, default_filter_level, std::io::sink, None);
         init_subscriber(subscriber);
     }
 });
@@ -32,6 +39,9 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    pub metrics: MetricsSettings,
+    pub base_url: String,
 }
 
 pub struct ConfirmationLinks {
@@ -43,7 +53,7 @@ impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue = 
-                try_execute_task(&self.db_pool, &self.email_client)
+                try_execute_task(&self.db_pool, &self.email_client, &self.base_url)
                     .await
                     .unwrap()
             {
@@ -67,6 +77,7 @@ impl TestApp {
     {
         self.api_client
             .post(&format!("{}/admin/password", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -82,6 +93,16 @@ impl TestApp {
     pub async fn get_admin_dashboard_html(&self) -> String {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
+    // The token the admin forms embed for the current session, empty when
+    // logged out.
+    pub async fn csrf_token(&self) -> String {
+        let html = self.get_admin_dashboard_html().await;
+        html.split(r#"name="csrf_token" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_owned()
+    }
     // Our tests will only look at the HTML page, therefore
     // we do not expose the underlying reqwest::Response
     pub async fn get_login_html(&self) -> String {
@@ -110,6 +131,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/logout", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request")
@@ -124,6 +146,57 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Use the public API of the application under test to create
+    /// an unconfirmed subscriber.
+    pub async fn create_unconfirmed_subscriber(&self, email: &str) -> ConfirmationLinks {
+        let body = serde_urlencoded::to_string(&serde_json::json!({
+            "name": "sang khuu",
+            "email": email
+        }))
+        .unwrap();
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .named("Create unconfirmed subscriber")
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+
+        // We now inspect the requests received by the mock Postmark server
+        // to retrieve the confirmation link and return it
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        self.get_confirmation_link(email_request)
+    }
+
+    pub async fn create_confirmed_subscriber(&self, email: &str) {
+        let confirmation_link = self.create_unconfirmed_subscriber(email).await;
+        reqwest::get(confirmation_link.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
             .get(&format!("{}/admin/newsletters", &self.address))
@@ -143,11 +216,448 @@ impl TestApp {
     {
         self.api_client
             .post(&format!("{}/admin/newsletters", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_data_request_export(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/data_requests/export", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_request_erase<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/data_requests/erase", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_data_requests_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/data_requests", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook.username,
+                Some(self.postmark_webhook.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_suppressions_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/suppressions", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_suppressions_upload(&self, csv: &str) -> reqwest::Response {
+        let boundary = "suppression-upload-boundary";
+        // Like browsers, send the token as a part of the form
+        let csrf_token = self.csrf_token().await;
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+            {csrf_token}\r\n\
+            --{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"suppressions.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n\
+            --{boundary}--\r\n"
+        );
+        self.api_client
+            .post(&format!("{}/admin/suppressions/upload", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences_html(&self, token: &str) -> String {
+        self.get_preferences(token).await.text().await.unwrap()
+    }
+
+    pub async fn post_preferences<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/subscriptions/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_topic(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/topics", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_users(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.get_users().await.text().await.unwrap()
+    }
+
+    pub async fn post_invite_user(&self, email: &str, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/users/invite", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("email", email), ("role", role)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_deactivate_user(&self, user_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/users/deactivate", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("user_id", user_id.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/invitations/accept", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn get_accept_invitation_html(&self, token: &str) -> String {
+        self.api_client
+            .get(&format!("{}/invitations/accept", &self.address))
+            .query(&[("invitation_token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/sessions/revoke", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("session_id", session_id.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/sessions/revoke_others", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/api_tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_create_api_token(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/api_tokens", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_revoke_api_token(&self, api_token_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/api_tokens/revoke", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("api_token_id", api_token_id.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    // Mint a token for the logged-in user and return it in clear text.
+    pub async fn create_api_token(&self) -> String {
+        let html = self.post_create_api_token("Test token").await.text().await.unwrap();
+        html.split(r#"<code id="api-token">"#)
+            .nth(1)
+            .and_then(|rest| rest.split('<').next())
+            .expect("The new API token is not shown.")
+            .to_owned()
+    }
+    // A separate client without cookies, API calls must work on the token alone.
+    pub fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, &format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(token)
+    }
+    pub async fn get_metrics(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/metrics", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_metrics_text(&self) -> String {
+        self.get_metrics(self.metrics.bearer_token.expose_secret())
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn get_webhooks(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/webhooks", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_webhooks_html(&self) -> String {
+        self.get_webhooks().await.text().await.unwrap()
+    }
+    pub async fn post_add_webhook(&self, url: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/webhooks", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("url", url)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_remove_webhook(&self, webhook_endpoint_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/webhooks/remove", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("webhook_endpoint_id", webhook_endpoint_id.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// Register an endpoint and return its signing secret.
+    pub async fn add_webhook(&self, url: &str) -> String {
+        let html = self.post_add_webhook(url).await.text().await.unwrap();
+        html.split(r#"<code id="webhook-secret">"#)
+            .nth(1)
+            .and_then(|rest| rest.split('<').next())
+            .expect("The webhook secret is not shown.")
+            .to_owned()
+    }
+    /// Attempt every webhook delivery that is due, retries scheduled for
+    /// later are left alone.
+    pub async fn dispatch_all_pending_webhooks(&self) {
+        let http_client = webhook_http_client();
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_deliver_webhook(&self.db_pool, &http_client)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+    pub async fn get_totp_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/totp", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_totp(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/totp/enable", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("code", code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/login/two_factor", &self.address))
+            .form(&[("code", code)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_account_email_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/email", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_account_email(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/email", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_request_password_reset_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/password_reset", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_password_reset_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/password_reset", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/password_reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    /// Reset links are sent after answering: wait until the email API has
+    /// received `count` requests and return them.
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..50 {
+            let requests = self.email_server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("The email API did not receive {} requests.", count);
+    }
+
+    pub async fn get_password_reset_confirm_html(&self, token: &str) -> String {
+        self.api_client
+            .get(&format!("{}/password_reset/confirm", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
     /// Extract the confirmation links embedded in the request to the email API.
     pub fn get_confirmation_link(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
@@ -172,7 +682,7 @@ impl TestApp {
 }
 //----------------------------------------------------------------
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
     pub password: String,
 }
@@ -192,6 +702,16 @@ impl TestUser {
         }))
         .await;
     }
+    pub async fn set_role(&self, db_pool: &PgPool, role: &str) {
+        sqlx::query!(
+            "UPDATE users SET role = $1 WHERE user_id = $2",
+            role,
+            self.user_id
+        )
+        .execute(db_pool)
+        .await
+        .expect("Failed to change the role of the test user");
+    }
     pub async fn store(&self, db_pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         // Match production parameters
@@ -218,6 +738,11 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after tweaking its configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -232,6 +757,9 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Every test logs in from 127.0.0.1, keep their login counters apart
+        c.login_throttle.key_prefix = format!("login_throttle_{}", Uuid::new_v4());
+        configure(&mut c);
         c
     };
     // Create and migrate the database
@@ -258,7 +786,10 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client()
+        email_client: configuration.email_client.client(),
+        postmark_webhook: configuration.postmark_webhook,
+        metrics: configuration.metrics,
+        base_url: configuration.application.base_url,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -0,0 +1,375 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use email_newsletter::configuration::{get_configuration, IdempotencySettings};
+use email_newsletter::idempotency::{
+    delete_expired_idempotency_keys, save_response, try_processing, IdempotencyKey, NextAction,
+    RequestFingerprint,
+};
+use email_newsletter::utils::see_other;
+use reqwest::Method;
+use sqlx::{Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+const RETENTION: Duration = Duration::from_secs(48 * 60 * 60);
+
+fn issue_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+fn newsletter_form(idempotency_key: &str) -> serde_json::Value {
+    let mut body = issue_body();
+    body["idempotency_key"] = idempotency_key.into();
+    body
+}
+
+async fn publish_with_key(test_app: &TestApp, idempotency_key: &str) -> reqwest::Response {
+    test_app
+        .post_publish_newsletter(&newsletter_form(idempotency_key))
+        .await
+}
+
+/// Claim the key the way a slow submission of the newsletter form by the
+/// test user would, the key is held until the transaction is committed or
+/// dropped.
+async fn start_processing(
+    test_app: &TestApp,
+    idempotency_key: &str,
+) -> Transaction<'static, Postgres> {
+    let settings = get_configuration().unwrap().idempotency;
+    let body = serde_urlencoded::to_string(newsletter_form(idempotency_key)).unwrap();
+    let fingerprint = RequestFingerprint::from_request(
+        "POST",
+        "/admin/newsletters",
+        "application/x-www-form-urlencoded",
+        body.as_bytes(),
+    );
+    let idempotency_key = IdempotencyKey::try_from(idempotency_key.to_owned()).unwrap();
+    match try_processing(
+        &test_app.db_pool,
+        &idempotency_key,
+        test_app.test_user.user_id,
+        &fingerprint,
+        &settings,
+    )
+    .await
+    .unwrap()
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        _ => panic!("The key was expected to be new."),
+    }
+}
+
+async fn age_idempotency_key(test_app: &TestApp, idempotency_key: &str, hours: i32) {
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET created_at = now() - make_interval(hours => $2)
+        WHERE idempotency_key = $1
+        "#,
+        idempotency_key,
+        hours,
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn stored_keys(test_app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT idempotency_key FROM idempotency ORDER BY idempotency_key")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.idempotency_key)
+        .collect()
+}
+
+async fn n_issues(test_app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn an_expired_key_is_processed_again() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    publish_with_key(&test_app, &idempotency_key).await;
+    publish_with_key(&test_app, &idempotency_key).await;
+    assert_eq!(n_issues(&test_app).await, 1);
+
+    age_idempotency_key(&test_app, &idempotency_key, 49).await;
+    publish_with_key(&test_app, &idempotency_key).await;
+
+    assert_eq!(n_issues(&test_app).await, 2);
+}
+
+#[tokio::test]
+async fn cleanup_deletes_only_expired_keys() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let (expired_key, fresh_key) = ("a-expired", "b-fresh");
+    publish_with_key(&test_app, expired_key).await;
+    publish_with_key(&test_app, fresh_key).await;
+    age_idempotency_key(&test_app, expired_key, 49).await;
+
+    let n_deleted = delete_expired_idempotency_keys(&test_app.db_pool, RETENTION, 100)
+        .await
+        .unwrap();
+
+    assert_eq!(n_deleted, 1);
+    assert_eq!(stored_keys(&test_app).await, [fresh_key]);
+}
+
+#[tokio::test]
+async fn cleanup_deletes_at_most_one_batch_at_a_time() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    for key in ["key-1", "key-2", "key-3"] {
+        publish_with_key(&test_app, key).await;
+        age_idempotency_key(&test_app, key, 49).await;
+    }
+
+    let first_batch = delete_expired_idempotency_keys(&test_app.db_pool, RETENTION, 2)
+        .await
+        .unwrap();
+    let second_batch = delete_expired_idempotency_keys(&test_app.db_pool, RETENTION, 2)
+        .await
+        .unwrap();
+
+    assert_eq!((first_batch, second_batch), (2, 1));
+    assert!(stored_keys(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn parallel_requests_with_the_same_key_publish_once() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let publish = || {
+        test_app
+            .api_request(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&issue_body())
+            .send()
+    };
+    let (r1, r2, r3) = tokio::join!(publish(), publish(), publish());
+
+    let mut bodies = Vec::new();
+    for response in [r1, r2, r3] {
+        let response = response.unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+        bodies.push(response.text().await.unwrap());
+    }
+    assert!(bodies.iter().all(|b| b == &bodies[0]));
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn a_retry_waits_for_the_request_in_flight() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let transaction = start_processing(&test_app, &idempotency_key).await;
+    let key = IdempotencyKey::try_from(idempotency_key.clone()).unwrap();
+    let user_id = test_app.test_user.user_id;
+    let first_request = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        save_response(transaction, &key, user_id, see_other("/admin/newsletters"))
+            .await
+            .unwrap();
+    };
+
+    let (_, response) = tokio::join!(first_request, publish_with_key(&test_app, &idempotency_key));
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // The saved response was replayed, nothing was published again
+    assert_eq!(n_issues(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn a_retry_is_rejected_when_the_request_in_flight_takes_too_long() {
+    let test_app = spawn_app_with(|c| {
+        c.idempotency = IdempotencySettings {
+            in_flight_wait_seconds: 1,
+            ..c.idempotency.clone()
+        }
+    })
+    .await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let _transaction = start_processing(&test_app, &idempotency_key).await;
+
+    let response = test_app
+        .api_request(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", &idempotency_key)
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "5");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_issue_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let publish = |body: serde_json::Value| {
+        test_app
+            .api_request(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&body)
+            .send()
+    };
+    publish(issue_body()).await.unwrap().error_for_status().unwrap();
+
+    let mut other_issue = issue_body();
+    other_issue["title"] = "Another title".into();
+    let response = publish(other_issue).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].is_string());
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn resubmitting_the_form_with_different_content_is_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    publish_with_key(&test_app, &idempotency_key).await;
+
+    let mut other_issue = issue_body();
+    other_issue["html_content"] = "<p>Edited after submitting</p>".into();
+    other_issue["idempotency_key"] = idempotency_key.into();
+    let response = test_app.post_publish_newsletter(&other_issue).await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn a_form_resubmitted_from_a_new_session_is_replayed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let submit = |fields: Vec<(&str, String)>| {
+        test_app
+            .api_client
+            .post(format!("{}/admin/newsletters", &test_app.address))
+            .form(&fields)
+            .send()
+    };
+    let first_token = test_app.csrf_token().await;
+    let response = submit(vec![
+        ("title", "Newsletter title".into()),
+        ("text_content", "Newsletter body as plain text".into()),
+        ("html_content", "<p>Newsletter body as HTML</p>".into()),
+        ("idempotency_key", idempotency_key.clone()),
+        ("csrf_token", first_token.clone()),
+    ])
+    .await
+    .unwrap();
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Logging in again starts a new session, with a new CSRF token
+    test_app.test_user.login(&test_app).await;
+    let second_token = test_app.csrf_token().await;
+    assert_ne!(first_token, second_token);
+    let response = submit(vec![
+        ("csrf_token", second_token),
+        ("html_content", "<p>Newsletter body as HTML</p>".into()),
+        ("idempotency_key", idempotency_key),
+        ("text_content", "Newsletter body as plain text".into()),
+        ("title", "Newsletter title".into()),
+    ])
+    .await
+    .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn an_api_retry_with_the_fields_in_another_order_is_replayed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = test_app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let publish = |body: &'static str| {
+        test_app
+            .api_request(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+    };
+    let first = publish(
+        r#"{"title": "Newsletter title", "text_content": "Text", "html_content": "<p>HTML</p>"}"#,
+    )
+    .await
+    .unwrap();
+    assert_eq!(first.status().as_u16(), 202);
+    let first_body = first.text().await.unwrap();
+
+    let retry = publish(
+        r#"{"html_content":"<p>HTML</p>","text_content":"Text","title":"Newsletter title"}"#,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(retry.status().as_u16(), 202);
+    assert_eq!(retry.text().await.unwrap(), first_body);
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn the_key_can_be_sent_in_a_header_instead_of_a_form_field() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    for _ in 0..2 {
+        let response = test_app
+            .api_client
+            .post(format!("{}/admin/newsletters", &test_app.address))
+            .header("Idempotency-Key", &idempotency_key)
+            .header("X-CSRF-Token", test_app.csrf_token().await)
+            .form(&issue_body())
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "/admin/newsletters");
+    }
+
+    assert_eq!(n_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn requests_without_a_key_are_rejected() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app.post_publish_newsletter(&issue_body()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(n_issues(&test_app).await, 0);
+}
@@ -1,4 +1,7 @@
 use crate::helpers::{spawn_app, assert_is_redirect_to};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use std::time::Duration;
 
 #[tokio::test]
 async fn error_flash_message_is_set_on_failure() {
@@ -44,3 +47,62 @@ async fn you_must_be_logged_in_to_access_admin_dashboard() {
     assert_is_redirect_to(&response, "/login");
     
 }
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_after_login() {
+    let test_app = spawn_app().await;
+    // A hash computed with cheaper parameters than the configured ones
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(test_app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        outdated_hash,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The upgrade happens in the background, give it a moment
+    let mut password_hash = outdated_hash.clone();
+    for _ in 0..50 {
+        password_hash = sqlx::query!(
+            "SELECT password_hash FROM users WHERE user_id = $1",
+            test_app.test_user.user_id
+        )
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .password_hash;
+        if password_hash != outdated_hash {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(password_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+
+    // The upgraded hash still matches the password
+    test_app.post_logout().await;
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,105 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+const LOCKOUT_MESSAGE: &str =
+    "<p><i>Too many failed login attempts. Please try again in 15 minutes.</i></p>";
+
+async fn fail_login(test_app: &TestApp, times: usize) {
+    let login_body = serde_json::json!({
+        "username": &test_app.test_user.username,
+        "password": "wrong-password"
+    });
+    for _ in 0..times {
+        let response = test_app.post_login(&login_body).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn an_account_is_locked_out_after_too_many_failed_attempts() {
+    let test_app = spawn_app().await;
+
+    fail_login(&test_app, 5).await;
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains(LOCKOUT_MESSAGE));
+
+    // Even the right password is turned away during the lockout
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains(LOCKOUT_MESSAGE));
+}
+
+#[tokio::test]
+async fn a_lockout_is_recorded_in_the_audit_log() {
+    let test_app = spawn_app().await;
+
+    fail_login(&test_app, 5).await;
+
+    let event = sqlx::query!("SELECT event_type, username, ip_address FROM audit_events")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the audit event.");
+    assert_eq!(event.event_type, "login_lockout");
+    assert_eq!(event.username.as_deref(), Some(test_app.test_user.username.as_str()));
+    assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    let test_app = spawn_app().await;
+
+    fail_login(&test_app, 4).await;
+    test_app.test_user.login(&test_app).await;
+    fail_login(&test_app, 4).await;
+
+    let html_page = test_app.get_login_html().await;
+    assert!(!html_page.contains("Too many failed login attempts"));
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_made_up_forwarded_for_header_does_not_get_around_the_ip_limit() {
+    let test_app = spawn_app_with(|c| c.login_throttle.max_failures_per_ip = 3).await;
+
+    for i in 0..3 {
+        let response = test_app
+            .api_client
+            .post(format!("{}/login", &test_app.address))
+            .header("X-Forwarded-For", format!("198.51.100.{}", i))
+            .form(&serde_json::json!({
+                "username": format!("someone-{}", i),
+                "password": "wrong-password"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Another username, another made up address, still the same client
+    let response = test_app
+        .api_client
+        .post(format!("{}/login", &test_app.address))
+        .header("X-Forwarded-For", "198.51.100.42")
+        .form(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains(LOCKOUT_MESSAGE));
+}
@@ -4,4 +4,23 @@ mod subscriptions;
 mod subscriptions_confirm;
 mod newsletter;
 mod login;
-mod change_password;
\ No newline at end of file
+mod change_password;
+mod data_requests;
+mod postmark_webhook;
+mod suppressions;
+mod subscriber_preferences;
+mod users;
+mod authorization;
+mod two_factor;
+mod password_reset;
+mod account_email;
+mod login_throttle;
+mod sessions;
+mod session_timeouts;
+
+mod csrf;
+mod api_v1;
+mod openapi;
+mod outbound_webhooks;
+mod idempotency;
+mod metrics;
@@ -0,0 +1,67 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Counters are shared by every application spawned by the test binary,
+// only check that the series exist.
+fn has_series(metrics: &str, series: &str) -> bool {
+    metrics.lines().any(|l| l.starts_with(series))
+}
+
+#[tokio::test]
+async fn metrics_require_the_bearer_token() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_metrics("not-the-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+}
+
+#[tokio::test]
+async fn metrics_cover_requests_deliveries_and_subscribers() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    let metrics = test_app.get_metrics_text().await;
+
+    assert!(has_series(
+        &metrics,
+        r#"http_requests_total{method="POST",route="/subscriptions",status="200"}"#
+    ));
+    assert!(has_series(
+        &metrics,
+        r#"http_request_duration_seconds_count{method="GET",route="/subscriptions/confirm"}"#
+    ));
+    assert!(metrics.contains(r#"subscribers{status="confirmed"} 1"#));
+    assert!(metrics.contains("issue_delivery_queue_depth 1"));
+    assert!(has_series(&metrics, r#"db_pool_connections{state="in_use"}"#));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+    let metrics = test_app.get_metrics_text().await;
+
+    assert!(metrics.contains("issue_delivery_queue_depth 0"));
+    assert!(has_series(&metrics, r#"issue_emails_total{outcome="sent"}"#));
+    assert!(has_series(
+        &metrics,
+        r#"email_provider_request_duration_seconds_count{outcome="success"}"#
+    ));
+}
@@ -0,0 +1,67 @@
+use crate::helpers::spawn_app;
+use email_newsletter::startup::API_ROUTES;
+use std::collections::BTreeSet;
+
+#[tokio::test]
+async fn the_openapi_document_is_served() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/api/openapi.json", &test_app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(document["paths"]["/api/v1/issues"]["post"].is_object());
+    assert!(document["components"]["schemas"]["FormData"].is_object());
+    assert!(document["components"]["securitySchemes"]["api_token"].is_object());
+}
+
+#[tokio::test]
+async fn the_docs_page_points_at_the_openapi_document() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::get(&format!("{}/api/docs", &test_app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("/api/openapi.json"));
+}
+
+#[tokio::test]
+async fn every_api_route_is_documented() {
+    let test_app = spawn_app().await;
+    let document: serde_json::Value = reqwest::get(&format!("{}/api/openapi.json", &test_app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let paths = document["paths"].as_object().unwrap();
+
+    let routes: BTreeSet<(String, String)> = API_ROUTES
+        .iter()
+        .map(|route| (route.method.as_str().to_lowercase(), route.path.to_owned()))
+        .collect();
+    for (method, path) in &routes {
+        assert!(
+            paths.get(path).and_then(|p| p.get(method)).is_some(),
+            "{} {} is not documented in the OpenAPI document.",
+            method.to_uppercase(),
+            path
+        );
+    }
+    // And the other way around, the document does not promise routes we do not have
+    for (path, operations) in paths {
+        for method in operations.as_object().unwrap().keys() {
+            assert!(
+                routes.contains(&(method.clone(), path.clone())),
+                "{} {} is documented but not registered.",
+                method.to_uppercase(),
+                path
+            );
+        }
+    }
+}
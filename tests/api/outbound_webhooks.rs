@@ -0,0 +1,340 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use email_newsletter::webhook_delivery_worker::MAX_DELIVERY_ATTEMPTS;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::Executor;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn recorded_event_types(test_app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT event_type FROM webhook_events ORDER BY occurred_at")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.event_type)
+        .collect()
+}
+
+fn header(request: &wiremock::Request, name: &str) -> String {
+    request.headers[&name.into()].last().as_str().to_owned()
+}
+
+struct DeliveryRow {
+    status: String,
+    n_attempts: i16,
+    is_due: bool,
+}
+
+async fn the_only_delivery(test_app: &TestApp) -> DeliveryRow {
+    sqlx::query_as!(
+        DeliveryRow,
+        r#"
+        SELECT status, n_attempts, execute_after <= now() AS "is_due!"
+        FROM webhook_deliveries
+        "#
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn an_owner_can_add_a_webhook_endpoint() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let secret = test_app.add_webhook("https://crm.example.com/hooks").await;
+
+    assert!(secret.starts_with("whsec_"));
+    let html_page = test_app.get_webhooks_html().await;
+    assert!(html_page.contains("https://crm.example.com/hooks"));
+    assert!(!html_page.contains(&secret));
+}
+
+#[tokio::test]
+async fn only_http_urls_are_accepted_as_endpoints() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app.post_add_webhook("ftp://crm.example.com").await;
+
+    assert_is_redirect_to(&response, "/admin/webhooks");
+    let html_page = test_app.get_webhooks_html().await;
+    assert!(html_page.contains("The endpoint must be an http or https URL."));
+}
+
+#[tokio::test]
+async fn an_editor_cannot_manage_webhooks() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "editor").await;
+    test_app.test_user.login(&test_app).await;
+
+    assert_eq!(test_app.get_webhooks().await.status().as_u16(), 403);
+    let response = test_app.post_add_webhook("https://crm.example.com/hooks").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn no_events_are_stored_without_endpoints() {
+    let test_app = spawn_app().await;
+
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    assert!(recorded_event_types(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscriber_lifecycle_events_are_recorded_once() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+
+    let confirmation_links = test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    // Following the link twice confirms only once
+    for _ in 0..2 {
+        reqwest::get(confirmation_links.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    let token = sqlx::query!("SELECT preferences_token FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .preferences_token;
+    test_app.post_unsubscribe(&token).await;
+
+    assert_eq!(
+        recorded_event_types(&test_app).await,
+        [
+            "subscriber.subscribed",
+            "subscriber.confirmed",
+            "subscriber.unsubscribed"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn only_bounces_and_complaints_that_stop_delivery_are_recorded() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+
+    for (record_type, bounce_type) in [
+        ("Bounce", "SoftBounce"),
+        ("Bounce", "HardBounce"),
+        ("SpamComplaint", "SpamComplaint"),
+    ] {
+        test_app
+            .post_postmark_webhook(&serde_json::json!({
+                "RecordType": record_type,
+                "Type": bounce_type,
+                "Email": "sangkhuudev@gmail.com",
+            }))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    assert_eq!(
+        recorded_event_types(&test_app).await,
+        ["subscriber.bounced", "subscriber.complained"]
+    );
+}
+
+#[tokio::test]
+async fn publishing_and_delivering_an_issue_are_recorded() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        recorded_event_types(&test_app).await,
+        ["issue.published", "issue.delivered"]
+    );
+}
+
+#[tokio::test]
+async fn deliveries_are_signed_with_the_endpoint_secret() {
+    let test_app = spawn_app().await;
+    let crm_server = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&crm_server)
+        .await;
+    test_app.test_user.login(&test_app).await;
+    let secret = test_app
+        .add_webhook(&format!("{}/hooks", crm_server.uri()))
+        .await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app.dispatch_all_pending_webhooks().await;
+
+    let request = &crm_server.received_requests().await.unwrap()[0];
+    let timestamp = header(request, "x-webhook-timestamp");
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(&request.body);
+    let expected_signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(
+        header(request, "x-webhook-signature"),
+        expected_signature
+    );
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["type"], "subscriber.subscribed");
+    assert_eq!(body["data"]["email"], "sangkhuudev@gmail.com");
+    let delivery = the_only_delivery(&test_app).await;
+    assert_eq!(delivery.status, "delivered");
+    assert_eq!(delivery.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later() {
+    let test_app = spawn_app().await;
+    let crm_server = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(500))
+        // The retry is not due yet when we dispatch again
+        .expect(1)
+        .mount(&crm_server)
+        .await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .add_webhook(&format!("{}/hooks", crm_server.uri()))
+        .await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app.dispatch_all_pending_webhooks().await;
+    test_app.dispatch_all_pending_webhooks().await;
+
+    let delivery = the_only_delivery(&test_app).await;
+    assert_eq!(delivery.status, "pending");
+    assert_eq!(delivery.n_attempts, 1);
+    assert!(!delivery.is_due);
+    let html_page = test_app.get_webhooks_html().await;
+    assert!(html_page.contains("subscriber.subscribed"));
+    assert!(html_page.contains("500 Internal Server Error"));
+}
+
+#[tokio::test]
+async fn deliveries_are_given_up_after_the_last_attempt() {
+    let test_app = spawn_app().await;
+    let crm_server = MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&crm_server)
+        .await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .add_webhook(&format!("{}/hooks", crm_server.uri()))
+        .await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    sqlx::query!(
+        "UPDATE webhook_deliveries SET n_attempts = $1",
+        MAX_DELIVERY_ATTEMPTS - 1
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    test_app.dispatch_all_pending_webhooks().await;
+
+    let delivery = the_only_delivery(&test_app).await;
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, MAX_DELIVERY_ATTEMPTS);
+}
+
+#[tokio::test]
+async fn removing_an_endpoint_cancels_its_pending_deliveries() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+    test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    let webhook_endpoint_id = sqlx::query!("SELECT webhook_endpoint_id FROM webhook_endpoints")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .webhook_endpoint_id;
+
+    let response = test_app.post_remove_webhook(webhook_endpoint_id).await;
+
+    assert_is_redirect_to(&response, "/admin/webhooks");
+    let html_page = test_app.get_webhooks_html().await;
+    assert!(html_page.contains("The webhook endpoint has been removed."));
+    assert_eq!(the_only_delivery(&test_app).await.status, "cancelled");
+}
+
+#[tokio::test]
+async fn an_issue_is_not_sent_again_when_recording_its_delivery_fails() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.add_webhook("https://crm.example.com/hooks").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    // Any further event fails to be stored
+    test_app
+        .db_pool
+        .execute("ALTER TABLE webhook_events ADD CONSTRAINT no_more_events CHECK (false) NOT VALID")
+        .await
+        .unwrap();
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // The queue is empty and the mock checks the email was sent once
+    assert_eq!(recorded_event_types(&test_app).await, ["issue.published"]);
+}
@@ -0,0 +1,205 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const TEST_USER_EMAIL: &str = "admin@example.com";
+
+/// Ask for a reset link for the test user and return the token it carries.
+async fn request_reset_token(test_app: &TestApp) -> String {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        TEST_USER_EMAIL,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+
+    let response = test_app.post_password_reset_request(TEST_USER_EMAIL).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let email_request = test_app.wait_for_emails(1).await.pop().unwrap();
+    let reset_link = test_app.get_confirmation_link(&email_request).html;
+    assert_eq!(reset_link.path(), "/password_reset/confirm");
+    let response = reqwest::get(reset_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    reset_link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+fn reset_form(token: &str, new_password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    })
+}
+
+#[tokio::test]
+async fn an_unknown_email_gets_the_same_answer_and_no_email() {
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_password_reset_request("nobody@example.com")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains(
+        "<p><i>If an account exists for this email, a link to reset its password is on its way.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn an_address_cannot_ask_for_more_than_three_reset_links() {
+    let test_app = spawn_app().await;
+    for _ in 0..3 {
+        let response = test_app
+            .post_password_reset_request("nobody@example.com")
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    let response = test_app
+        .post_password_reset_request("NOBODY@example.com")
+        .await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let html_page = test_app.get_request_password_reset_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many password reset requests. Please try again later.</i></p>"));
+
+    // Other addresses are not affected
+    let response = test_app
+        .post_password_reset_request("somebody@example.com")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_user_can_log_in_with_the_password_set_through_a_reset_link() {
+    let test_app = spawn_app().await;
+    let token = request_reset_token(&test_app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = test_app
+        .post_password_reset(&reset_form(&token, &new_password))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_reset_token_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    let token = request_reset_token(&test_app).await;
+
+    let response = test_app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_reset_token_is_rejected() {
+    let test_app = spawn_app().await;
+    let token = request_reset_token(&test_app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let response = test_app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn new_passwords_must_match() {
+    let test_app = spawn_app().await;
+    let token = request_reset_token(&test_app).await;
+
+    let response = test_app
+        .post_password_reset(&serde_json::json!({
+            "token": token,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_is_redirect_to(
+        &response,
+        &format!("/password_reset/confirm?token={}", token),
+    );
+}
+
+#[tokio::test]
+async fn a_common_password_is_rejected() {
+    let test_app = spawn_app().await;
+    let token = request_reset_token(&test_app).await;
+
+    let response = test_app
+        .post_password_reset(&reset_form(&token, "passwordpassword"))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/password_reset/confirm?token={}", token),
+    );
+
+    let html_page = test_app.get_password_reset_confirm_html(&token).await;
+    assert!(html_page.contains(
+        "<p><i>The new password is too common - please choose another one.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_out_existing_sessions() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let response = test_app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = request_reset_token(&test_app).await;
+    test_app
+        .post_password_reset(&reset_form(&token, &Uuid::new_v4().to_string()))
+        .await;
+
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
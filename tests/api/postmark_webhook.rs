@@ -0,0 +1,183 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_status(test_app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+fn bounce(email: &str, bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807u64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Name": "Hard bounce",
+        "MessageStream": "outbound",
+        "Description": "The server was unable to deliver your message.",
+        "Email": email,
+        "From": "test@example.com",
+        "BouncedAt": "2023-10-08T21:28:07Z",
+    })
+}
+
+#[tokio::test]
+async fn requests_without_valid_credentials_are_rejected() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .api_client
+        .post(&format!("{}/webhooks/postmark", &test_app.address))
+        .basic_auth("postmark", Some("wrong-password"))
+        .json(&bounce("sangkhuudev@gmail.com", "HardBounce"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        r#"Basic realm="webhooks""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn hard_bounces_mark_the_subscriber_as_bounced() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    let response = test_app
+        .post_postmark_webhook(&bounce("sangkhuudev@gmail.com", "HardBounce"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&test_app, "sangkhuudev@gmail.com").await, "bounced");
+    let events = sqlx::query!("SELECT record_type, bounce_type FROM bounce_events")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].bounce_type.as_deref(), Some("HardBounce"));
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_undo_a_hard_bounce() {
+    let test_app = spawn_app().await;
+    let confirmation_link = test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    reqwest::get(confirmation_link.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    test_app
+        .post_postmark_webhook(&bounce("sangkhuudev@gmail.com", "HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber_status(&test_app, "sangkhuudev@gmail.com").await, "bounced");
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_without_suppressing_the_subscriber() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app
+        .post_postmark_webhook(&bounce("sangkhuudev@gmail.com", "SoftBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber_status(&test_app, "sangkhuudev@gmail.com").await, "confirmed");
+}
+
+#[tokio::test]
+async fn spam_complaints_mark_the_subscriber_as_complained() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "Email": "sangkhuudev@gmail.com",
+            "BouncedAt": "2023-10-08T21:28:07Z",
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(subscriber_status(&test_app, "sangkhuudev@gmail.com").await, "complained");
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_bounced_subscribers() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app
+        .post_postmark_webhook(&bounce("sangkhuudev@gmail.com", "HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app.test_user.login(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn hard_bounces_add_the_address_to_the_suppression_list() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    test_app
+        .post_postmark_webhook(&bounce("sangkhuudev@gmail.com", "HardBounce"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT source FROM suppressed_emails WHERE email = 'sangkhuudev@gmail.com'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.source, "bounce");
+}
@@ -0,0 +1,72 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use std::time::Duration;
+
+async fn spawn_app_with_instant_idle_timeout() -> TestApp {
+    spawn_app_with(|c| c.session.idle_timeout_minutes = 0).await
+}
+
+#[tokio::test]
+async fn an_idle_session_expires_and_the_user_is_told_so() {
+    let test_app = spawn_app_with_instant_idle_timeout().await;
+    test_app.test_user.login(&test_app).await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let response = test_app.get_publish_newsletter().await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fnewsletters");
+
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your session expired. Please log in again.</i></p>"));
+    // The session is gone for good
+    let revoked = sqlx::query!("SELECT revoked_at IS NOT NULL AS \"revoked!\" FROM user_sessions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(revoked.revoked);
+}
+
+#[tokio::test]
+async fn the_user_returns_to_the_requested_page_after_logging_in_again() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password,
+            "next": "/admin/newsletters",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn the_login_form_keeps_track_of_the_requested_page() {
+    let test_app = spawn_app().await;
+
+    let html_page = test_app
+        .api_client
+        .get(&format!("{}/login?next=%2Fadmin%2Fnewsletters", &test_app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(r#"<input hidden type="text" name="next" value="/admin/newsletters">"#));
+}
+
+#[tokio::test]
+async fn the_user_is_never_sent_outside_of_the_admin_area() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password,
+            "next": "https://evil.example.com/admin/",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
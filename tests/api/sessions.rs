@@ -0,0 +1,131 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+/// Log the test user in from a second browser.
+async fn log_in_elsewhere(test_app: &TestApp) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent("Other browser")
+        .build()
+        .unwrap();
+    let response = client
+        .post(&format!("{}/login", &test_app.address))
+        .form(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard_status(test_app: &TestApp, client: &reqwest::Client) -> u16 {
+    client
+        .get(&format!("{}/admin/dashboard", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .status()
+        .as_u16()
+}
+
+async fn other_session_id(test_app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT session_id FROM user_sessions WHERE user_agent = 'Other browser'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the other session.")
+        .session_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_sessions().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn active_sessions_are_listed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    log_in_elsewhere(&test_app).await;
+
+    let html_page = test_app.get_sessions_html().await;
+
+    assert!(html_page.contains("Other browser"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains(&other_session_id(&test_app).await.to_string()));
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let other_client = log_in_elsewhere(&test_app).await;
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 200);
+
+    let response = test_app
+        .post_revoke_session(other_session_id(&test_app).await)
+        .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    let html_page = test_app.get_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!html_page.contains("Other browser"));
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    // The current session is untouched
+    assert_eq!(test_app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn you_cannot_revoke_a_session_that_is_not_yours() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    test_app.post_revoke_session(Uuid::new_v4()).await;
+
+    let html_page = test_app.get_sessions_html().await;
+    assert!(html_page
+        .contains("<p><i>The session does not exist or has already ended.</i></p>"));
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_logged_out_at_once() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let other_client = log_in_elsewhere(&test_app).await;
+
+    let response = test_app.post_revoke_other_sessions().await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    let html_page = test_app.get_sessions_html().await;
+    assert!(html_page.contains("<p><i>1 other session(s) have been logged out.</i></p>"));
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    assert_eq!(test_app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_other_sessions() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let other_client = log_in_elsewhere(&test_app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = test_app
+        .post_change_password(&serde_json::json!({
+            "current_password": &test_app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    assert_eq!(get_dashboard_status(&test_app, &other_client).await, 303);
+    assert_eq!(test_app.get_admin_dashboard().await.status().as_u16(), 200);
+}
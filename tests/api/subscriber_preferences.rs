@@ -0,0 +1,316 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribe and confirm `sangkhuudev@gmail.com`, returning its preferences token.
+async fn confirmed_subscriber_token(test_app: &TestApp) -> String {
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    sqlx::query!("SELECT preferences_token FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch the preferences token.")
+        .preferences_token
+}
+
+async fn publish_newsletter(test_app: &TestApp, topic_id: Option<Uuid>) {
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "topic_id": topic_id.map(|id| id.to_string()).unwrap_or_default(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+fn default_preferences(token: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("token", token.to_owned()),
+        ("name", "sang khuu".into()),
+        ("delivery_frequency", "every_issue".into()),
+        // What browsers send when the pause is left alone
+        ("pause_weeks", "".into()),
+    ]
+}
+
+#[tokio::test]
+async fn every_issue_contains_a_link_to_the_preference_page() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    publish_newsletter(&test_app, None).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = format!("/subscriptions/preferences?token={}", token);
+    assert!(body["HtmlBody"].as_str().unwrap().contains(&link));
+    assert!(body["TextBody"].as_str().unwrap().contains(&link));
+}
+
+#[tokio::test]
+async fn an_unknown_preferences_token_is_rejected_with_a_401() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_preferences("not-a-real-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[1].1 = "viet sang".into();
+
+    let response = test_app.post_preferences(&form).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={}", token),
+    );
+
+    let html_page = test_app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "viet sang");
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[1].1 = "<script>".into();
+
+    test_app.post_preferences(&form).await;
+
+    let html_page = test_app.get_preferences_html(&token).await;
+    assert!(html_page.contains("The name cannot contain any of"));
+    assert!(!html_page.contains("&lt;script&gt;"));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "sang khuu");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_new_issues() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app.post_unsubscribe(&token).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={}", token),
+    );
+    let html_page = test_app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p><i>You have been unsubscribed.</i></p>"));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, None).await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_new_issues() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[3].1 = "2".into();
+    test_app.post_preferences(&form).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, None).await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_pause_also_holds_back_deliveries_queued_before_it() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, None).await;
+
+    let mut form = default_preferences(&token);
+    form[3].1 = "2".into();
+    test_app.post_preferences(&form).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+}
+
+async fn is_paused(test_app: &TestApp) -> bool {
+    sqlx::query!(r#"SELECT COALESCE(paused_until > now(), false) AS "paused!" FROM subscriptions"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .paused
+}
+
+#[tokio::test]
+async fn saving_other_preferences_does_not_end_a_pause() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[3].1 = "4".into();
+    test_app.post_preferences(&form).await;
+    let html_page = test_app.get_preferences_html(&token).await;
+    assert!(html_page.contains("Resume deliveries now"));
+
+    let mut form = default_preferences(&token);
+    form[1].1 = "viet sang".into();
+    test_app.post_preferences(&form).await;
+
+    assert!(is_paused(&test_app).await);
+}
+
+#[tokio::test]
+async fn subscribers_can_resume_deliveries_before_the_end_of_a_pause() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[3].1 = "4".into();
+    test_app.post_preferences(&form).await;
+
+    form[3].1 = "0".into();
+    test_app.post_preferences(&form).await;
+
+    assert!(!is_paused(&test_app).await);
+}
+
+#[tokio::test]
+async fn weekly_digest_deliveries_are_held_until_the_next_batch() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[2].1 = "weekly_digest".into();
+    test_app.post_preferences(&form).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, None).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    let queued = sqlx::query!("SELECT execute_after > now() AS \"held!\" FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.held);
+}
+
+#[tokio::test]
+async fn weekly_digest_subscribers_get_the_held_issues_in_a_single_email() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let mut form = default_preferences(&token);
+    form[2].1 = "weekly_digest".into();
+    test_app.post_preferences(&form).await;
+    for title in ["First issue", "Second issue"] {
+        test_app
+            .post_publish_newsletter(&serde_json::json!({
+                "title": title,
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string(),
+            }))
+            .await;
+    }
+    // Monday comes
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    let first = text_body.find("First issue").unwrap();
+    let second = text_body.find("Second issue").unwrap();
+    assert!(first < second);
+}
+
+#[tokio::test]
+async fn subscribers_do_not_receive_topics_they_opted_out_of() {
+    let test_app = spawn_app().await;
+    let token = confirmed_subscriber_token(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    assert_is_redirect_to(&test_app.post_topic("Rust").await, "/admin/topics");
+    assert_is_redirect_to(&test_app.post_topic("Databases").await, "/admin/topics");
+    let topics = sqlx::query!("SELECT topic_id, name FROM topics")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    let topic_id = |name: &str| topics.iter().find(|t| t.name == name).unwrap().topic_id;
+
+    // Keep "Rust" checked and leave "Databases" out
+    let mut form = default_preferences(&token);
+    form.push(("topic", topic_id("Rust").to_string()));
+    test_app.post_preferences(&form).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    publish_newsletter(&test_app, Some(topic_id("Rust"))).await;
+    publish_newsletter(&test_app, Some(topic_id("Databases"))).await;
+    publish_newsletter(&test_app, None).await;
+    test_app.dispatch_all_pending_emails().await;
+}
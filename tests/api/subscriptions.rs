@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 use wiremock::matchers::{path, method};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -120,4 +120,144 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let response = test_app.post_subscriptions(body.into()).await;
 
     assert_eq!(response.status().as_u16(), 500);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn subscribe_records_a_consent_event() {
+    let test_app = spawn_app().await;
+    let body = "name=sang%20khuu&email=sangkhuudev%40gmail.com&source=footer_form";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+
+    let saved = sqlx::query!(
+        "SELECT event_type, source, consent_text_version, ip_address FROM consent_events",
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent event");
+    assert_eq!(saved.event_type, "subscribed");
+    assert_eq!(saved.source, "footer_form");
+    assert_eq!(saved.consent_text_version, "2023-10-01");
+    assert!(saved.ip_address.is_some());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_replies_with_json() {
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "sang khuu",
+            "email": "sangkhuudev@gmail.com",
+            "source": "landing_page",
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.email, "sangkhuudev@gmail.com");
+    assert_eq!(saved.name, "sang khuu");
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field_of_a_json_request() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "<script>",
+            "email": "not-an-email",
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["errors"],
+        serde_json::json!([
+            {
+                "field": "name",
+                "code": "forbidden_characters",
+                "message": r#"The name cannot contain any of / ( ) " < > \ { }"#,
+            },
+            {
+                "field": "email",
+                "code": "invalid_format",
+                "message": "The email address is not valid.",
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_json_error_for_a_malformed_json_body() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscriptions_json(&serde_json::json!({ "name": "sang khuu" }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], serde_json::Value::Null);
+    assert_eq!(body["errors"][0]["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn form_requests_keep_getting_plain_text_errors() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscriptions("name=sang&email=not-an-email".into())
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(response.text().await.unwrap(), "The email address is not valid.");
+}
+
+#[tokio::test]
+async fn configured_origins_are_allowed_to_subscribe_from_the_browser() {
+    let test_app = spawn_app_with(|c| {
+        c.application.cors_allowed_origins = vec!["https://www.example.com".into()]
+    })
+    .await;
+    let preflight = |origin: &'static str| {
+        test_app
+            .api_client
+            .request(
+                reqwest::Method::OPTIONS,
+                &format!("{}/subscriptions", &test_app.address),
+            )
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+    };
+
+    let response = preflight("https://www.example.com").await.unwrap();
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://www.example.com"
+    );
+
+    let response = preflight("https://evil.example.com").await.unwrap();
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
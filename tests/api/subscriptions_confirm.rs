@@ -62,3 +62,55 @@ async fn clicking_on_the_confirmation_link_confirms_subscriber() {
     assert_eq!(saved.name, "sang khuu");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirming_a_subscription_records_a_consent_event() {
+    let test_app = spawn_app().await;
+    let confirmation_link = test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    reqwest::Client::new()
+        .get(confirmation_link.html)
+        .header("User-Agent", "consent-test-agent")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let events = sqlx::query!(
+        "SELECT event_type, user_agent FROM consent_events ORDER BY occurred_at",
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent events");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, "subscribed");
+    assert_eq!(events[1].event_type, "confirmed");
+    assert_eq!(events[1].user_agent.as_deref(), Some("consent-test-agent"));
+}
+
+#[tokio::test]
+async fn following_the_link_twice_records_a_single_confirmed_event() {
+    let test_app = spawn_app().await;
+    let confirmation_link = test_app
+        .create_unconfirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+
+    for _ in 0..2 {
+        reqwest::get(confirmation_link.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    let events = sqlx::query!(
+        "SELECT event_type FROM consent_events WHERE event_type = 'confirmed'",
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved consent events");
+    assert_eq!(events.len(), 1);
+}
@@ -0,0 +1,106 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_suppression_list() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_suppression(&serde_json::json!({
+            "email": "sangkhuudev@gmail.com",
+            "source": "manual",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn legally_suppressed_addresses_cannot_subscribe() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let response = test_app
+        .post_suppression(&serde_json::json!({
+            "email": "SangKhuuDev@gmail.com",
+            "source": "legal",
+            "reason": "Court order",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_subscriptions("name=sang%20khuu&email=sangkhuudev%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    let test_app = spawn_app().await;
+    test_app
+        .create_confirmed_subscriber("sangkhuudev@gmail.com")
+        .await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .post_suppression(&serde_json::json!({
+            "email": "sangkhuudev@gmail.com",
+            "source": "manual",
+        }))
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn a_suppression_csv_can_be_uploaded() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app
+        .post_suppressions_upload(
+            "email,source,reason\n\
+            first@example.com,legal,Erasure request\n\
+            second@example.com\n\
+            not-an-email,manual,",
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = test_app.get_suppressions_html().await;
+    assert!(html_page.contains(
+        "<p><i>2 addresses have been added to the suppression list.</i></p>"
+    ));
+    assert!(html_page.contains(
+        "<p><i>The following lines were skipped because they are invalid: 4.</i></p>"
+    ));
+    let saved = sqlx::query!("SELECT email, source FROM suppressed_emails ORDER BY email")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "first@example.com");
+    assert_eq!(saved[0].source, "legal");
+    assert_eq!(saved[1].source, "manual");
+}
@@ -0,0 +1,191 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use email_newsletter::authentication::generate_totp_code;
+use secrecy::Secret;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The SHA1 seed from RFC 6238, base32-encoded.
+const TOTP_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+fn current_code(secret: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    generate_totp_code(&Secret::new(secret.to_owned()), now).unwrap()
+}
+
+fn first_code_block(html_page: &str) -> String {
+    html_page
+        .split("<code>")
+        .nth(1)
+        .and_then(|s| s.split("</code>").next())
+        .unwrap()
+        .to_owned()
+}
+
+async fn enroll_test_user(test_app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET totp_secret = $1 WHERE user_id = $2",
+        TOTP_SECRET,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn post_credentials(test_app: &TestApp) -> reqwest::Response {
+    test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password,
+        }))
+        .await
+}
+
+#[tokio::test]
+async fn a_user_can_enroll_with_a_code_from_their_authenticator() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    let html_page = test_app.get_totp_html().await;
+    assert!(html_page.contains("otpauth://totp/Newsletter:"));
+    assert!(html_page.contains("<svg"));
+    let secret = first_code_block(&html_page);
+
+    let response = test_app.post_enable_totp(&current_code(&secret)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Two-factor authentication has been enabled."));
+    assert_eq!(html_page.matches("<li><code>").count(), 10);
+
+    let stored = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.totp_secret, Some(secret));
+}
+
+#[tokio::test]
+async fn enrollment_is_rejected_with_a_wrong_code() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app.get_totp_html().await;
+
+    let response = test_app.post_enable_totp("000000").await;
+    assert_is_redirect_to(&response, "/admin/totp");
+
+    let html_page = test_app.get_totp_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is invalid.</i></p>"));
+}
+
+#[tokio::test]
+async fn enrolled_users_are_not_logged_in_until_they_enter_a_code() {
+    let test_app = spawn_app().await;
+    enroll_test_user(&test_app).await;
+
+    let response = post_credentials(&test_app).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let response = test_app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = test_app.post_two_factor(&current_code(TOTP_SECRET)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let test_app = spawn_app().await;
+    enroll_test_user(&test_app).await;
+    let code = current_code(TOTP_SECRET);
+
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    test_app.post_logout().await;
+
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_send_the_user_back_to_the_login_page() {
+    let test_app = spawn_app().await;
+    enroll_test_user(&test_app).await;
+    post_credentials(&test_app).await;
+
+    for _ in 0..4 {
+        let response = test_app.post_two_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+    let response = test_app.post_two_factor("000000").await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The password has to be entered again
+    let response = test_app.post_two_factor(&current_code(TOTP_SECRET)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logging_in_again_does_not_reset_the_count_of_wrong_codes() {
+    let test_app = spawn_app().await;
+    enroll_test_user(&test_app).await;
+    for _ in 0..2 {
+        post_credentials(&test_app).await;
+        for _ in 0..2 {
+            let response = test_app.post_two_factor("000000").await;
+            assert_is_redirect_to(&response, "/login/two_factor");
+        }
+    }
+
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor("000000").await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Even the right code is refused until the lockout is over
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor(&current_code(TOTP_SECRET)).await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts. Please try again in 15 minutes."));
+    let event = sqlx::query!("SELECT event_type, user_id, details FROM audit_events")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.event_type, "login_lockout");
+    assert_eq!(event.user_id, Some(test_app.test_user.user_id));
+    assert_eq!(
+        event.details.as_deref(),
+        Some("Locked out by second_factor")
+    );
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_replace_a_totp_code_once() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let secret = first_code_block(&test_app.get_totp_html().await);
+    let html_page = test_app
+        .post_enable_totp(&current_code(&secret))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let recovery_code = first_code_block(&html_page);
+    test_app.post_logout().await;
+
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor(&recovery_code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    test_app.post_logout().await;
+
+    post_credentials(&test_app).await;
+    let response = test_app.post_two_factor(&recovery_code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
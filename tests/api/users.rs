@@ -0,0 +1,250 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Invite `email` as the logged-in owner and return the invitation token.
+async fn invite(test_app: &TestApp, email: &str, role: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    let response = test_app.post_invite_user(email, role).await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let invitation_link = test_app.get_confirmation_link(&email_request).html;
+    assert_eq!(invitation_link.path(), "/invitations/accept");
+    invitation_link
+        .query_pairs()
+        .find(|(k, _)| k == "invitation_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_users().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_users() {
+    let test_app = spawn_app().await;
+    test_app.test_user.set_role(&test_app.db_pool, "editor").await;
+    test_app.test_user.login(&test_app).await;
+
+    let response = test_app.get_users().await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = test_app.post_invite_user("collaborator@example.com", "owner").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_invited_user_can_set_a_password_and_log_in() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = invite(&test_app, "collaborator@example.com", "viewer").await;
+
+    let html_page = test_app.get_users_html().await;
+    assert!(html_page.contains("<p><i>An invitation has been sent to collaborator@example.com.</i></p>"));
+
+    let password = Uuid::new_v4().to_string();
+    let response = test_app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": token,
+            "username": "collaborator",
+            "password": password,
+            "password_check": password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": "collaborator",
+            "password": password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT role, email FROM users WHERE username = 'collaborator'")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.role, "viewer");
+    assert_eq!(user.email.as_deref(), Some("collaborator@example.com"));
+}
+
+#[tokio::test]
+async fn invitation_tokens_are_stored_hashed() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = invite(&test_app, "collaborator@example.com", "viewer").await;
+
+    let row = sqlx::query!("SELECT token_hash FROM user_invitations")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(row.token_hash, token);
+    let html_page = test_app.get_accept_invitation_html(&token).await;
+    assert!(html_page.contains("collaborator@example.com"));
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_used_once() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = invite(&test_app, "collaborator@example.com", "editor").await;
+    let accept = |username: &'static str| {
+        serde_json::json!({
+            "invitation_token": token,
+            "username": username,
+            "password": "a-long-password",
+            "password_check": "a-long-password",
+        })
+    };
+
+    let response = test_app.post_accept_invitation(&accept("first")).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app.post_accept_invitation(&accept("second")).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_second_invitation_to_a_taken_email_cannot_be_accepted() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let first_token = invite(&test_app, "collaborator@example.com", "editor").await;
+    let second_token = invite(&test_app, "collaborator@example.com", "viewer").await;
+    let accept = |token: &str, username: &str| {
+        serde_json::json!({
+            "invitation_token": token,
+            "username": username,
+            "password": "a-long-password",
+            "password_check": "a-long-password",
+        })
+    };
+
+    let response = test_app
+        .post_accept_invitation(&accept(&first_token, "first"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = test_app
+        .post_accept_invitation(&accept(&second_token, "second"))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/invitations/accept?invitation_token={}", second_token),
+    );
+
+    let html_page = test_app.get_accept_invitation_html(&second_token).await;
+    assert!(html_page.contains(
+        "<p><i>collaborator@example.com already belongs to another account.</i></p>"
+    ));
+    let users = sqlx::query!("SELECT username FROM users WHERE email IS NOT NULL")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].username, "first");
+}
+
+#[tokio::test]
+async fn an_invited_user_cannot_pick_a_password_containing_their_username() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let token = invite(&test_app, "collaborator@example.com", "editor").await;
+
+    let response = test_app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": token,
+            "username": "collaborator",
+            "password": "collaborator-2026",
+            "password_check": "collaborator-2026",
+        }))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/invitations/accept?invitation_token={}", token),
+    );
+
+    let html_page = test_app.get_accept_invitation_html(&token).await;
+    assert!(html_page.contains(
+        "<p><i>The new password must not contain your username.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn deactivated_users_cannot_log_in() {
+    let test_app = spawn_app().await;
+    let other_user_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, role)
+        SELECT $1, 'other-owner', password_hash, 'owner' FROM users WHERE user_id = $2",
+        other_user_id,
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.test_user.login(&test_app).await;
+
+    // Log in as the second owner, who then deactivates the test user
+    test_app
+        .post_login(&serde_json::json!({
+            "username": "other-owner",
+            "password": &test_app.test_user.password,
+        }))
+        .await;
+    let response = test_app.post_deactivate_user(test_app.test_user.user_id).await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": &test_app.test_user.username,
+            "password": &test_app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn owners_cannot_deactivate_themselves() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    test_app.post_deactivate_user(test_app.test_user.user_id).await;
+
+    let html_page = test_app.get_users_html().await;
+    assert!(html_page.contains("<p><i>You cannot deactivate your own account.</i></p>"));
+}
+
+#[tokio::test]
+async fn a_deactivated_user_loses_access_on_their_next_request() {
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    sqlx::query!(
+        "UPDATE users SET is_active = false WHERE user_id = $1",
+        test_app.test_user.user_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let response = test_app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}